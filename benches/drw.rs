@@ -0,0 +1,85 @@
+//! Times DRW on the interpreter's bit-row screen against drawing the same sprites pixel by pixel into a 2048-element PixelState
+//! array, the way the screen was stored before. The interpreter's DRW time is the time to run a drawing loop less the time to run
+//! the same loop with LD V2 V3 in place of the DRW, so that fetching and decoding isn't counted against it. Run with
+//! `cargo bench --bench drw`.
+
+use rusty_chip8::chip8::{Chip8, PixelState};
+use std::time::{Duration, Instant};
+
+const SPRITE: [u8; 15] = [0xF1, 0x80, 0x3C, 0xFF, 0x81, 0x7E, 0x18, 0xA5, 0x5A, 0xC3, 0x3C, 0x66, 0x99, 0xE7, 0x0F];
+const DRAWS: u32 = 200_000;
+const RUNS:  u32 = 10;
+
+//LD I 300; DRW V0 V1 F; ADD V0 03; ADD V1 05; JP 202
+const DRAWING_LOOP: [u8; 10] = [0xA3, 0x00, 0xD0, 0x1F, 0x70, 0x03, 0x71, 0x05, 0x12, 0x02];
+//LD I 300; LD V2 V3; ADD V0 03; ADD V1 05; JP 202
+const EMPTY_LOOP:   [u8; 10] = [0xA3, 0x00, 0x82, 0x30, 0x70, 0x03, 0x71, 0x05, 0x12, 0x02];
+
+fn time_loop(program: &[u8]) -> Duration
+{
+    let mut device: Chip8 = Chip8::builder().build();
+    device.load_rom(program).unwrap();
+    device.write_memory(0x300, &SPRITE);
+
+    let start: Instant = Instant::now();
+    device.run_budget(1 + 4 * DRAWS);
+    start.elapsed()
+}
+
+// Runs the timing RUNS times and keeps the fastest, to keep other work on the machine out of the comparison.
+fn fastest(timing: impl Fn() -> Duration) -> Duration
+{
+    (0..RUNS).map(|_| timing()).min().unwrap()
+}
+
+fn time_pixel_by_pixel() -> Duration
+{
+    let mut screen: [PixelState; 2048] = [PixelState::Unlit; 2048];
+    let mut x: u8 = 0;
+    let mut y: u8 = 0;
+    let mut collision: bool = false;
+
+    let start: Instant = Instant::now();
+    for _ in 0..DRAWS
+    {
+        collision = false;
+        for (row, byte) in SPRITE.iter().enumerate()
+        {
+            for bit in 0..8
+            {
+                if (byte & (0x80 >> bit)) == 0
+                {
+                    continue;
+                }
+
+                let pixel: usize = (((y as usize) + row) % 32) * 64 + ((x as usize) + bit) % 64;
+                screen[pixel] = match screen[pixel]
+                {
+                    PixelState::Lit   =>
+                    {
+                        collision = true;
+                        PixelState::Unlit
+                    }
+                    PixelState::Unlit => PixelState::Lit
+                };
+            }
+        }
+        x = x.wrapping_add(3);
+        y = y.wrapping_add(5);
+    }
+    let elapsed: Duration = start.elapsed();
+
+    std::hint::black_box((collision, screen));
+    elapsed
+}
+
+fn main()
+{
+    let bit_rows:       Duration = fastest(|| time_loop(&DRAWING_LOOP)).saturating_sub(fastest(|| time_loop(&EMPTY_LOOP)));
+    let pixel_by_pixel: Duration = fastest(time_pixel_by_pixel);
+
+    println!("{} draws of an 8x15 sprite, fastest of {} runs", DRAWS, RUNS);
+    println!("  bit rows:       {:?}", bit_rows);
+    println!("  pixel by pixel: {:?}", pixel_by_pixel);
+    println!("  speedup: {:.2}x", pixel_by_pixel.as_secs_f64() / bit_rows.as_secs_f64());
+}
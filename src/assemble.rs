@@ -0,0 +1,284 @@
+//! For converting Chip-8 assembly language, as produced by chip8_disassembly::disassemble, back into machine code.
+
+use crate::chip8::PROGRAM_BASE;
+
+/// Errors returned by assemble. Line numbers start at 1.
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum AssembleError
+{
+    UnknownMnemonic { line: usize, mnemonic: String }, // The mnemonic isn't part of the instruction set
+    BadOperands { line: usize },                       // The operands are missing, extra, or out of range for the mnemonic
+    DuplicateLabel { line: usize, label: String }      // The label was already defined on an earlier line
+}
+
+// The mnemonics accepted by assemble, as emitted by the disassembler.
+const MNEMONICS: [&str; 31] =
+[
+    "CLS", "RET", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS", "JP", "CALL", "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND",
+    "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE", "AUDIO", "PITCH"
+];
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError>
+{
+    //! Assembles the provided source into a program to be loaded at PROGRAM_BASE (0x200). Each line holds at most one
+    //! instruction, written the way disassemble writes it: a mnemonic followed by space separated operands, such as "LD 1 FF",
+    //! "JP 200", or "DRW 1 2 5". Numbers are hexadecimal, and anything after a ';' is a comment.
+    //!
+    //! A line may start with a "label:" definition, and labels can be used in place of the address operand of SYS, JP, CALL, and
+    //! LD I. Labels are not case sensitive. A label past the end of the 4KB address space can't be encoded, and is reported as
+    //! AssembleError::BadOperands where it is used.
+    //!
+    //! Registers are written as a hex digit, optionally prefixed with 'V'. As disassemble writes "LD 1 2" both for LD V1, 2 and
+    //! LD V1, V2, a bare second operand of LD, ADD, SE, and SNE is taken to be a value; write "LD 1 V2" for the register form.
+    //! Likewise "LD F 1" and "LD B 1" are the Fx29 and Fx33 instructions, so write "LD VF 1" to load a value below 10 into VF.
+    //! RND's mask is optional and defaults to FF.
+
+    //First pass: find the address of every label.
+    let mut labels: Vec<(String, u16)> = Vec::new();
+    let mut address: u16 = PROGRAM_BASE;
+    for (line_index, line) in source.lines().enumerate()
+    {
+        let (label, instruction) = split_line(line);
+        if let Some(label) = label
+        {
+            if labels.iter().any(|(defined, _)| defined.eq_ignore_ascii_case(label))
+            {
+                return Err(AssembleError::DuplicateLabel { line: line_index + 1, label: String::from(label) });
+            }
+            labels.push((String::from(label), address));
+        }
+
+        if !instruction.is_empty()
+        {
+            address = address.wrapping_add(2);
+        }
+    }
+
+    //Second pass: encode each instruction, now that every label can be resolved.
+    let mut program: Vec<u8> = Vec::new();
+    for (line_index, line) in source.lines().enumerate()
+    {
+        let (_, instruction) = split_line(line);
+        let mut words = instruction.split_whitespace();
+        let mnemonic: String = match words.next()
+        {
+            Some(mnemonic) => mnemonic.to_uppercase(),
+            None           => continue
+        };
+        let operands: Vec<&str> = words.collect();
+
+        if !MNEMONICS.contains(&mnemonic.as_str())
+        {
+            return Err(AssembleError::UnknownMnemonic { line: line_index + 1, mnemonic });
+        }
+
+        let opcode: u16 = encode(&mnemonic, &operands, &labels).ok_or(AssembleError::BadOperands { line: line_index + 1 })?;
+        program.push((opcode >> 8) as u8);
+        program.push((opcode & 0xFF) as u8);
+    }
+
+    Ok(program)
+}
+
+// Splits a line of source into its label, if any, and its instruction, with the comment removed.
+fn split_line(line: &str) -> (Option<&str>, &str)
+{
+    let code: &str = line.split(';').next().unwrap_or("").trim();
+    match code.find(':')
+    {
+        Some(colon) => (Some(code[..colon].trim()), code[(colon + 1)..].trim()),
+        None        => (None, code)
+    }
+}
+
+// Encodes one instruction, or returns None if its operands don't fit the mnemonic.
+fn encode(mnemonic: &str, operands: &[&str], labels: &[(String, u16)]) -> Option<u16>
+{
+    let operands: Vec<String> = operands.iter().map(|operand| operand.to_uppercase()).collect();
+    let operands: Vec<&str>   = operands.iter().map(|operand| operand.as_str()).collect();
+
+    let address = |operand: &str| -> Option<u16>
+    {
+        match labels.iter().find(|(label, _)| label.to_uppercase() == operand)
+        {
+            Some((_, address)) if *address <= 0x0FFF => Some(*address),
+            Some(_)                                  => None,
+            None               => parse_hex(operand, 0xFFF)
+        }
+    };
+    let reg   = |operand: &str| -> Option<u16> { parse_hex(operand.strip_prefix('V').unwrap_or(operand), 0xF) };
+    let v_reg = |operand: &str| -> Option<u16> { parse_hex(operand.strip_prefix('V')?, 0xF) };
+    let kk    = |operand: &str| -> Option<u16> { parse_hex(operand, 0xFF) };
+    let n     = |operand: &str| -> Option<u16> { parse_hex(operand, 0xF) };
+
+    let opcode: u16 = match (mnemonic, operands.as_slice())
+    {
+        ("CLS",  [])          => 0x00E0,
+        ("RET",  [])          => 0x00EE,
+        ("SCD",  [lines])     => 0x00C0 | n(lines)?,
+        ("SCR",  [])          => 0x00FB,
+        ("SCL",  [])          => 0x00FC,
+        ("EXIT", [])          => 0x00FD,
+        ("LOW",  [])          => 0x00FE,
+        ("HIGH", [])          => 0x00FF,
+        ("SYS",  [a])         => address(a)?,
+        ("JP",   ["V0", a])   => 0xB000 | address(a)?,
+        ("JP",   [a])         => 0x1000 | address(a)?,
+        ("CALL", [a])         => 0x2000 | address(a)?,
+        ("SE",   [x, y]) if v_reg(y).is_some() => 0x5000 | (reg(x)? << 8) | (v_reg(y)? << 4),
+        ("SE",   [x, k])      => 0x3000 | (reg(x)? << 8) | kk(k)?,
+        ("SNE",  [x, y]) if v_reg(y).is_some() => 0x9000 | (reg(x)? << 8) | (v_reg(y)? << 4),
+        ("SNE",  [x, k])      => 0x4000 | (reg(x)? << 8) | kk(k)?,
+        ("SAVE", [x, y])      => 0x5002 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("LOAD", [x, y])      => 0x5003 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("LD",   ["I", a])    => 0xA000 | address(a)?,
+        ("LD",   [x, "DT"])   => 0xF007 | (reg(x)? << 8),
+        ("LD",   [x, "K"])    => 0xF00A | (reg(x)? << 8),
+        ("LD",   [x, "[I]"])  => 0xF065 | (reg(x)? << 8),
+        ("LD",   ["DT", x])   => 0xF015 | (reg(x)? << 8),
+        ("LD",   ["ST", x])   => 0xF018 | (reg(x)? << 8),
+        ("LD",   ["F", x]) if reg(x).is_some() => 0xF029 | (reg(x)? << 8),
+        ("LD",   ["B", x]) if reg(x).is_some() => 0xF033 | (reg(x)? << 8),
+        ("LD",   ["[I]", x])  => 0xF055 | (reg(x)? << 8),
+        ("LD",   [x, y]) if v_reg(y).is_some() => 0x8000 | (reg(x)? << 8) | (v_reg(y)? << 4),
+        ("LD",   [x, k])      => 0x6000 | (reg(x)? << 8) | kk(k)?,
+        ("ADD",  ["I", x])    => 0xF01E | (reg(x)? << 8),
+        ("ADD",  [x, y]) if v_reg(y).is_some() => 0x8004 | (reg(x)? << 8) | (v_reg(y)? << 4),
+        ("ADD",  [x, k])      => 0x7000 | (reg(x)? << 8) | kk(k)?,
+        ("OR",   [x, y])      => 0x8001 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("AND",  [x, y])      => 0x8002 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("XOR",  [x, y])      => 0x8003 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("SUB",  [x, y])      => 0x8005 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("SHR",  [x, y])      => 0x8006 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("SUBN", [x, y])      => 0x8007 | (reg(x)? << 8) | (reg(y)? << 4),
+        ("SHL",  [x, y])      => 0x800E | (reg(x)? << 8) | (reg(y)? << 4),
+        ("RND",  [x])         => 0xC0FF | (reg(x)? << 8),
+        ("RND",  [x, k])      => 0xC000 | (reg(x)? << 8) | kk(k)?,
+        ("DRW",  [x, y, rows]) => 0xD000 | (reg(x)? << 8) | (reg(y)? << 4) | n(rows)?,
+        ("SKP",  [x])         => 0xE09E | (reg(x)? << 8),
+        ("SKNP", [x])         => 0xE0A1 | (reg(x)? << 8),
+        ("PLANE", [planes])   => 0xF001 | (n(planes)? << 8),
+        ("AUDIO", [])         => 0xF002,
+        ("PITCH", [x])        => 0xF03A | (reg(x)? << 8),
+        _                     => return None
+    };
+
+    Some(opcode)
+}
+
+// Parses a hexadecimal number, returning None if it isn't one or is greater than max.
+fn parse_hex(text: &str, max: u16) -> Option<u16>
+{
+    match u16::from_str_radix(text, 16)
+    {
+        Ok(value) if value <= max => Some(value),
+        _                         => None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::chip8_disassembly::disassemble;
+    use crate::instruction::{decode, Instruction};
+
+    // Returns true if the opcode disassembles the same as another instruction, which assemble picks instead: LD, ADD, SE, and SNE
+    // with two registers read as the versions taking a value, and LD VF, n and LD VB, n with n below 10 read as Fx29 and Fx33.
+    fn disassembles_ambiguously(opcode: u16) -> bool
+    {
+        match decode(opcode)
+        {
+            Instruction::LdVxVy {..} | Instruction::AddVxVy {..} | Instruction::SeVxVy {..} | Instruction::SneVxVy {..} => true,
+            Instruction::LdVx {x, kk} => ((x == 0xB) || (x == 0xF)) && (kk <= 0xF),
+            _                         => false
+        }
+    }
+
+    #[test]
+    fn assembling_the_disassembly_gives_back_every_opcode()
+    {
+        for opcode in 0..=0xFFFF
+        {
+            if let Instruction::Unknown(_) = decode(opcode)
+            {
+                continue;
+            }
+
+            let source: String = disassemble(opcode);
+            let assembled: Vec<u8> = assemble(&source)
+                .unwrap_or_else(|error| panic!("{:04X} disassembles as {}: {:?}", opcode, source, error));
+            if disassembles_ambiguously(opcode)
+            {
+                let reassembled: u16 = ((assembled[0] as u16) << 8) | (assembled[1] as u16);
+                assert_eq!(disassemble(reassembled), source, "{:04X} disassembles as {}", opcode, source);
+            }
+            else
+            {
+                assert_eq!(assembled, vec![(opcode >> 8) as u8, (opcode & 0xFF) as u8], "{:04X} disassembles as {}", opcode, source);
+            }
+        }
+    }
+
+    #[test]
+    fn bare_register_numbers_are_accepted()
+    {
+        assert_eq!(assemble("LD 1 FF\nJP 200\nDRW 1 2 5"), Ok(vec![0x61, 0xFF, 0x12, 0x00, 0xD1, 0x25]));
+        assert_eq!(assemble("OR 1 2\nSKP A\nLD 3 [I]"), Ok(vec![0x81, 0x21, 0xEA, 0x9E, 0xF3, 0x65]));
+        assert_eq!(assemble("LD V1 FF\nDRW V1 V2 5"), Ok(vec![0x61, 0xFF, 0xD1, 0x25]));
+    }
+
+    #[test]
+    fn a_bare_second_operand_is_a_value()
+    {
+        assert_eq!(assemble("LD 1 2"), Ok(vec![0x61, 0x02]));
+        assert_eq!(assemble("LD 1 V2"), Ok(vec![0x81, 0x20]));
+        assert_eq!(assemble("ADD 1 2"), Ok(vec![0x71, 0x02]));
+        assert_eq!(assemble("ADD 1 V2"), Ok(vec![0x81, 0x24]));
+        assert_eq!(assemble("SE 1 2"), Ok(vec![0x31, 0x02]));
+        assert_eq!(assemble("SNE 1 V2"), Ok(vec![0x91, 0x20]));
+        assert_eq!(assemble("LD F 1"), Ok(vec![0xF1, 0x29]));
+        assert_eq!(assemble("LD VF 1"), Ok(vec![0x6F, 0x01]));
+        assert_eq!(assemble("RND 1"), Ok(vec![0xC1, 0xFF]));
+        assert_eq!(assemble("LD 1 G"), Err(AssembleError::BadOperands { line: 1 }));
+    }
+
+    #[test]
+    fn labels_are_resolved_case_insensitively()
+    {
+        assert_eq!(assemble("start: CLS\nJP START"), Ok(vec![0x00, 0xE0, 0x12, 0x00]));
+        assert_eq!(assemble("loop: CLS\nLOOP: CLS"), Err(AssembleError::DuplicateLabel { line: 2, label: String::from("LOOP") }));
+    }
+
+    #[test]
+    fn labels_past_the_address_space_are_rejected()
+    {
+        //0x700 two byte instructions fill 0x200 to 0xFFF, so the label lands on 0x1000.
+        let source: String = format!("{}end: CLS\nJP end", "CLS\n".repeat(0x700));
+        assert_eq!(assemble(&source), Err(AssembleError::BadOperands { line: 0x702 }));
+    }
+
+    #[test]
+    fn a_program_survives_assembling_and_disassembling()
+    {
+        let source: &str = "start: LD 0 0 ; column\n\
+                            LD 1 0\n\
+                            loop: LD F 0\n\
+                            DRW 0 1 5\n\
+                            ADD V0 5\n\
+                            SE 0 3C\n\
+                            JP loop\n\
+                            CALL sub\n\
+                            JP start\n\
+                            sub: RND 2 F\n\
+                            RET";
+        let program: Vec<u8> = assemble(source).unwrap();
+        let listing: Vec<(u16, String)> = crate::chip8_disassembly::disassemble_program(&program, PROGRAM_BASE, false);
+        let lines: Vec<&str> = listing.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(lines, vec!["LD 0 0", "LD 1 0", "LD F 0", "DRW 0 1 5", "ADD 0 5", "SE 0 3C", "JP 204", "CALL 212", "JP 200", "RND 2 F",
+                               "RET"]);
+        assert_eq!(assemble(&lines.join("\n")), Ok(program));
+    }
+}
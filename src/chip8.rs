@@ -3,23 +3,98 @@
 //347kB
 extern crate rand;
 use rand::Rng;
+use rand_core::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+use std::collections::BTreeSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::chip8_disassembly::disassemble;
+use crate::instruction::{decode, Instruction};
+
+/// Size of the device's memory in bytes.
+pub const MEMORY_SIZE: usize = 4096;
+/// Size of the device's memory in bytes in XO-CHIP mode.
+pub const XO_CHIP_MEMORY_SIZE: usize = 65536;
+/// Address that programs are loaded at and that execution starts from.
+pub const PROGRAM_BASE: u16 = 0x200;
+/// Size of the default font in bytes. (16 glyphs, 5 bytes each)
+pub const FONT_SIZE: usize = 80;
+/// Address that the default font is loaded at. (0x050-0x09F, where most interpreters and tools expect it)
+pub const FONT_BASE: u16 = 0x50;
+
+/// A typical number of instructions to run per frame with run_frame and fast_forward. This is cycles_for_framerate(700, 60), 700
+/// instructions per second at 60 frames per second rounded to the nearest whole instruction. (so really 720 per second)
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 12;
+
+/// Largest screen height in pixels. (the Super-CHIP high resolution mode, 128x64)
+const MAX_SCREEN_HEIGHT: usize = 64;
+
+/// The default XO-CHIP palette: black, white, light gray, and dark gray. (RGBA)
+const DEFAULT_PALETTE: [[u8; 4]; 4] =
+[
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF]
+];
 
 pub struct Chip8
 {
     device_state:       CpuState,
-    opcode:             u8,
+    opcode:             u16,
     index:              u16,
     program_counter:    u16,
-    timer_delay:        f32,
-    buzzer_delay:       f32,
+    timer_delay:        u8,
+    buzzer_delay:       u8,
+    delay_fraction:     f32,
+    buzzer_fraction:    f32,
     stack_pointer:      u8,
     temp_vx:            u8,
     stack:             [u16; 16],
     general_registers: [u8; 16],
-    memory:            [u8; 4096],
+    memory:             Vec<u8>,
+    memory_mask:        u16,
+    program_len:        usize,
+    font_base:          u16,
     keypad:            [KeyState; 16],
     temp_keypad:       [KeyState; 16],
-    screen:            [PixelState; 64 * 32]
+    raw_keypad:        [KeyState; 16],
+    key_bounce_ticks:  [u16; 16],
+    key_debounce:       u16,
+    key_labels:        [String; 16],
+    screen:            [u128; MAX_SCREEN_HEIGHT],
+    second_plane:      [u128; MAX_SCREEN_HEIGHT],
+    resolution:         Resolution,
+    start_hires:        bool,
+    selected_planes:    u8,
+    palette:           [[u8; 4]; 4],
+    super_chip:         bool,
+    xo_chip:            bool,
+    audio_pattern:     [u8; 16],
+    audio_pitch:        u8,
+    instructions_since_draw: u32,
+    collisions_this_frame: u32,
+    instructions_executed: u64,
+    timer_ticks:        u64,
+    speed_multiplier:   f32,
+    quirks:             Quirks,
+    strict_memory:      bool,
+    index_from_font:    bool,
+    last_instruction_skipped: bool,
+    last_key_wait:      Option<(u8, u8)>,
+    last_vf_source:     Option<VfSource>,
+    last_sprite:        Option<(u8, u8, Vec<u8>)>,
+    rng:                Pcg64,
+    register_watches:   Vec<RegisterWatch>,
+    executed_opcode_kinds: Vec<&'static str>,
+    seek_origin:        Option<Box<Chip8Snapshot>>,
+    key_log:            Vec<(u64, u8, KeyState)>,
+    key_events:         Vec<(u8, KeyState)>,
+    frame_pokes:        Vec<(u16, u8)>,
+    frame_history:      Vec<Chip8Snapshot>,
+    frame_history_depth: usize,
+    custom_rng:         Option<Box<dyn Chip8Rng>>,
+    warning_hook:       Option<Box<dyn FnMut(Warning)>>
 }
 
 impl Default for Chip8 
@@ -31,50 +106,439 @@ impl Default for Chip8
             device_state:          CpuState::Ready,
             opcode:             0,
             index:              0,
-            program_counter:    0x200,
-            timer_delay:        0.0,
-            buzzer_delay:       0.0,
+            program_counter:    PROGRAM_BASE,
+            timer_delay:        0,
+            buzzer_delay:       0,
+            delay_fraction:     0.0,
+            buzzer_fraction:    0.0,
             stack_pointer:      0,
             temp_vx:            0,
             stack:             [0; 16],
             general_registers: [0; 16],
-            memory:            [0; 4096],
+            memory:             vec![0; XO_CHIP_MEMORY_SIZE],
+            memory_mask:        (MEMORY_SIZE - 1) as u16,
+            program_len:        0,
+            font_base:          FONT_BASE,
             keypad:            [KeyState::Unpressed; 16],
             temp_keypad:       [KeyState::Unpressed; 16],
-            screen:            [PixelState::Unlit; 64 * 32]
+            raw_keypad:        [KeyState::Unpressed; 16],
+            key_bounce_ticks:  [0; 16],
+            key_debounce:       0,
+            key_labels:        ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F"].map(String::from),
+            screen:            [0; MAX_SCREEN_HEIGHT],
+            second_plane:      [0; MAX_SCREEN_HEIGHT],
+            resolution:         Resolution::Low,
+            start_hires:        false,
+            selected_planes:    0b01,
+            palette:           DEFAULT_PALETTE,
+            super_chip:         false,
+            xo_chip:            false,
+            audio_pattern:     [0; 16],
+            audio_pitch:        64,
+            instructions_since_draw: 0,
+            collisions_this_frame: 0,
+            instructions_executed: 0,
+            timer_ticks:        0,
+            speed_multiplier:   1.0,
+            quirks:             Quirks::default(),
+            strict_memory:      false,
+            index_from_font:    false,
+            last_instruction_skipped: false,
+            last_key_wait:      None,
+            last_vf_source:     None,
+            last_sprite:        None,
+            rng:                Pcg64::seed_from_u64(rand::thread_rng().gen::<u64>()),
+            register_watches:   Vec::new(),
+            executed_opcode_kinds: Vec::new(),
+            seek_origin:        None,
+            key_log:            Vec::new(),
+            key_events:         Vec::new(),
+            frame_pokes:        Vec::new(),
+            frame_history:      Vec::new(),
+            frame_history_depth: 0,
+            custom_rng:         None,
+            warning_hook:       None
         } 
     }
 }
 
-///! Used to set the state of the Chip-8's keyboard
+/// Builds a Chip8 with the indicated configuration. The built device has been hard reset, so the default font is loaded.
+#[derive(Default)]
+pub struct Chip8Builder
+{
+    quirks:      Quirks,
+    super_chip:  bool,
+    xo_chip:     bool,
+    start_hires: bool
+}
+
+impl Chip8Builder
+{
+    /// Returns a builder for a device with the default configuration.
+    pub fn new() -> Self
+    {
+        Chip8Builder::default()
+    }
+
+    /// Sets the quirks used when executing instructions. (see Chip8::set_quirks)
+    pub fn quirks(mut self, quirks: Quirks) -> Self
+    {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Enables or disables Super-CHIP mode. (see Chip8::set_super_chip)
+    pub fn super_chip(mut self, enabled: bool) -> Self
+    {
+        self.super_chip = enabled;
+        self
+    }
+
+    /// Enables or disables XO-CHIP mode. (see Chip8::set_xo_chip)
+    pub fn xo_chip(mut self, enabled: bool) -> Self
+    {
+        self.xo_chip = enabled;
+        self
+    }
+
+    /// Sets whether hard_reset leaves the display in the 128x64 high resolution mode rather than the 64x32 low resolution mode.
+    /// Some Super-CHIP interpreters start in high resolution.
+    pub fn start_hires(mut self, enabled: bool) -> Self
+    {
+        self.start_hires = enabled;
+        self
+    }
+
+    /// Returns the configured device.
+    pub fn build(self) -> Chip8
+    {
+        let mut device: Chip8 = Chip8
+        {
+            quirks:      self.quirks,
+            super_chip:  self.super_chip,
+            start_hires: self.start_hires,
+            ..Chip8::default()
+        };
+        device.set_xo_chip(self.xo_chip);
+        device.hard_reset();
+
+        device
+    }
+}
+
+/// Used to set the state of the Chip-8's keyboard
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
 #[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeyState
 {
     Pressed,
     Unpressed
 }
 
-///! Used to indicate the state of a pixel on the Chip-8's screen.
+/// Used to indicate the state of a pixel on the Chip-8's screen.
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PixelState
 {
     Lit,
     Unlit
 }
 
+/// The display resolution. Low is the original 64x32 display; High is the Super-CHIP 128x64 display.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resolution
+{
+    Low,
+    High
+}
+
+/// The instruction sets a device can run, as returned by Chip8::variant.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Variant
+{
+    Chip8,     // The original instruction set
+    SuperChip, // Chip-8 plus the Super-CHIP instructions
+    XoChip     // Super-CHIP plus the XO-CHIP instructions
+}
+
+/// Toggles for behaviors that differ between Chip-8 interpreters. All quirks are disabled by default, except clear_on_mode_switch.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks
+{
+    pub draw_advances_i:      bool, // DRW adds the sprite height to I after drawing
+    pub lenient_stack:        bool, // RET on an empty stack and CALL on a full stack wrap the stack pointer around instead of faulting
+    pub clip_sprites:         bool, // DRW drops sprite pixels past the screen's edges instead of wrapping them to the other side
+    pub clear_on_mode_switch: bool, // 00FE and 00FF clear the screen. When disabled, the screen is scaled to the new resolution instead
+    pub shift_uses_vy:        bool  // SHR and SHL copy Vy into Vx before shifting, as on the COSMAC VIP
+}
+
+impl Default for Quirks
+{
+    fn default() -> Self
+    {
+        Quirks
+        {
+            draw_advances_i:      false,
+            lenient_stack:        false,
+            clip_sprites:         false,
+            clear_on_mode_switch: true,
+            shift_uses_vy:        false
+        }
+    }
+}
+
+/// Sets of quirks matching well-known interpreters, as applied by Chip8::auto_configure_quirks.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum QuirkProfile
+{
+    Modern,   // Quirks::default()
+    Cosmac,   // The original COSMAC VIP interpreter: shifts read Vy and sprites are clipped
+    SuperChip // Super-CHIP 1.1: sprites are clipped and mode switches keep the screen
+}
+
+impl QuirkProfile
+{
+    /// Returns the quirks used by this profile.
+    pub fn quirks(self) -> Quirks
+    {
+        match self
+        {
+            QuirkProfile::Modern    => Quirks::default(),
+            QuirkProfile::Cosmac    => Quirks { shift_uses_vy: true, clip_sprites: true, ..Quirks::default() },
+            QuirkProfile::SuperChip => Quirks { clip_sprites: true, clear_on_mode_switch: false, ..Quirks::default() }
+        }
+    }
+}
+
+/// Known ROMs, by the CRC-32 of the whole ROM file, and the profile they need to run correctly, as used by
+/// Chip8::auto_configure_quirks. Only add entries whose checksum has been computed from a verified dump of the ROM; frontends
+/// that know more ROMs can pass their own table to Chip8::auto_configure_quirks_from.
+const KNOWN_ROM_PROFILES: &[(u32, QuirkProfile)] =
+&[
+    (0xC46CA868, QuirkProfile::Modern) // IBM Logo (132 bytes), the usual first test ROM
+];
+
+/// Non-fatal conditions reported through the warning hook. Execution continues normally after a warning.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Warning
+{
+    SpriteFromFontRegion,      // DRW read sprite data from the font region while I was not set by Fx29. (strict memory only)
+    MisalignedFetch(u16),      // An instruction was fetched from an odd address. Holds the program counter.
+    ProgramCounterWrapped(u16) // The program counter ran past the end of memory and wrapped to the start. Holds the old program counter.
+}
+
+/// The instructions that set VF as a side effect, as returned by Chip8::last_vf_source.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VfSource
+{
+    Add,  // ADD Vx, Vy (carry)
+    Sub,  // SUB Vx, Vy (NOT borrow)
+    Subn, // SUBN Vx, Vy (NOT borrow)
+    Shr,  // SHR Vx (bit shifted out)
+    Shl,  // SHL Vx (bit shifted out)
+    Draw  // DRW Vx, Vy, n (collision)
+}
+
+/// The values of the delay and sound (buzzer) timers, as read and written together by Chip8::timers and Chip8::set_timers.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct TimerState
+{
+    pub delay: u8,
+    pub sound: u8
+}
+
+/// Errors returned when loading a program.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum LoadError
+{
+    TooLarge { len: usize } // The program or font, of len bytes, doesn't fit in memory after its load address
+}
+
+/// Errors returned when accessing the device at a memory address.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum AddressError
+{
+    OutOfRange(u16) // The address is past the end of memory
+}
+
+/// Errors returned when accessing the general registers directly.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum RegError
+{
+    InvalidRegister(u8) // The register number was greater than 0xF
+}
+
+/// Errors that stop the device. A faulted device executes nothing until it is reset.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fault
+{
+    StackUnderflow, // RET was executed with an empty stack
+    StackOverflow   // CALL was executed with all 16 stack slots in use
+}
+
+/// What the device is doing, as returned by Chip8::cpu_state.
 #[derive(PartialEq)]
 #[derive(Clone, Copy)]
-enum CpuState
+#[derive(Debug)]
+#[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuState
+{
+    Ready,              // Executing instructions normally
+    WaitingForKeypress, // Stopped at Fx0A until a key is pressed
+    Halted,             // Stopped by the Super-CHIP EXIT instruction until the device is reset
+    Faulted(Fault)      // Stopped by a fault until the device is reset
+}
+
+/// A copy of the device's state, as returned by Chip8::snapshot. Configuration (quirks, modes, palette, and hooks) is not included.
+/// With the serde feature, deserializing a snapshot whose memory, screen, or stack is the wrong size fails with an error, so any
+/// snapshot that deserializes can be passed to restore.
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chip8Snapshot
+{
+    device_state:       CpuState,
+    opcode:             u16,
+    index:              u16,
+    program_counter:    u16,
+    timer_delay:        u8,
+    buzzer_delay:       u8,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_stack_pointer"))]
+    stack_pointer:      u8,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_register_number"))]
+    temp_vx:            u8,
+    stack:             [u16; 16],
+    general_registers: [u8; 16],
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_memory"))]
+    memory:             Vec<u8>,
+    keypad:            [KeyState; 16],
+    temp_keypad:       [KeyState; 16],
+    raw_keypad:        [KeyState; 16],
+    key_bounce_ticks:  [u16; 16],
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_plane"))]
+    screen:             Vec<u128>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_plane"))]
+    second_plane:       Vec<u128>,
+    resolution:         Resolution,
+    selected_planes:    u8,
+    audio_pattern:     [u8; 16],
+    audio_pitch:        u8,
+    instructions_since_draw: u32,
+    collisions_this_frame: u32,
+    instructions_executed: u64,
+    timer_ticks:        u64,
+    index_from_font:    bool,
+    last_instruction_skipped: bool,
+    last_key_wait:      Option<(u8, u8)>,
+    last_vf_source:     Option<VfSource>,
+    last_sprite:        Option<(u8, u8, Vec<u8>)>,
+    rng:                Pcg64
+}
+
+// Deserializers for the Chip8Snapshot fields that restore relies on being in range, so that a damaged or hand-edited save state is
+// rejected when it is read rather than panicking when it is restored.
+#[cfg(feature = "serde")]
+fn deserialize_memory<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error>
+{
+    let memory: Vec<u8> = serde::Deserialize::deserialize(deserializer)?;
+    if (memory.len() != MEMORY_SIZE) && (memory.len() != XO_CHIP_MEMORY_SIZE)
+    {
+        return Err(serde::de::Error::invalid_length(memory.len(), &"4096 or 65536 bytes of memory"));
+    }
+
+    Ok(memory)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_plane<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u128>, D::Error>
+{
+    let plane: Vec<u128> = serde::Deserialize::deserialize(deserializer)?;
+    if plane.len() != MAX_SCREEN_HEIGHT
+    {
+        return Err(serde::de::Error::invalid_length(plane.len(), &"64 screen rows"));
+    }
+
+    Ok(plane)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_stack_pointer<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error>
+{
+    let stack_pointer: u8 = serde::Deserialize::deserialize(deserializer)?;
+    if stack_pointer > 16
+    {
+        return Err(serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(stack_pointer as u64), &"at most 16"));
+    }
+
+    Ok(stack_pointer)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_register_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error>
+{
+    let register: u8 = serde::Deserialize::deserialize(deserializer)?;
+    if register > 0xF
+    {
+        return Err(serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(register as u64), &"a register from 0 to F"));
+    }
+
+    Ok(register)
+}
+
+/// A source of random bytes for RND, as set with Chip8::set_rng.
+pub trait Chip8Rng
+{
+    /// Returns the next random byte.
+    fn next_u8(&mut self) -> u8;
+}
+
+// A register being watched by Chip8::watch_register, along with its most recent values. (oldest first)
+struct RegisterWatch
 {
-    Ready,
-    WaitingForKeypress
+    register: u8,
+    depth:    usize,
+    history:  Vec<u8>
 }
 
 // pc_Increment is used to indicate what the next program counter value needs to be.
 #[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
 enum pc_Increment
 {
     Next,  // 2 needs to be added to the program counter
@@ -84,35 +548,115 @@ enum pc_Increment
 
 impl Chip8
 {
-    ///! Performs a soft reset. (clears all registers and sets the PC to 0x200)
+    /// Returns a builder for configuring a new device.
+    pub fn builder() -> Chip8Builder
+    {
+        Chip8Builder::new()
+    }
+
+    /// Performs a soft reset. (clears all registers and sets the PC to 0x200)
     pub fn soft_reset(&mut self)
     {
         self.opcode          = 0x000;
         self.index           = 0x000;
-        self.program_counter = 0x200;
-        self.timer_delay     = 0.000;
-        self.buzzer_delay    = 0.000;
+        self.program_counter = PROGRAM_BASE;
+        self.timer_delay     = 0x000;
+        self.buzzer_delay    = 0x000;
+        self.delay_fraction  = 0.0;
+        self.buzzer_fraction = 0.0;
         self.stack_pointer   = 0x000;
         self.device_state    = CpuState::Ready;
-
-        return;
+        self.index_from_font = false;
+        self.last_instruction_skipped = false;
+        self.instructions_since_draw = 0;
+        self.collisions_this_frame   = 0;
+        self.instructions_executed   = 0;
+        self.timer_ticks     = 0;
+        self.selected_planes = 0b01;
+        self.last_key_wait   = None;
+        self.last_vf_source  = None;
+        self.last_sprite     = None;
+        self.executed_opcode_kinds.clear();
+        self.seek_origin     = None;
+        self.key_log.clear();
+        self.frame_history.clear();
     }
 
-    ///! Performs a hard reset. (resets the device's registers, memory, screen, keyboard, and font data)
+    /// Performs a hard reset. (resets the device's registers, memory, screen, keyboard, and font data)
     pub fn hard_reset(&mut self)
     {
         self.soft_reset();
-        for i in 0..15        {self.stack[i]             = 0x00}
-        for i in 0..15        {self.general_registers[i] = 0x00}
-        for i in 0..4096      {self.memory[i]            = 0x00}
+        for i in 0..16        {self.stack[i]             = 0x00}
+        for i in 0..16        {self.general_registers[i] = 0x00}
+        for i in 0..XO_CHIP_MEMORY_SIZE {self.memory[i]  = 0x00}
+        self.program_len = 0;
         for i in 0..16        {self.keypad[i]            = KeyState::Unpressed}
         for i in 0..16        {self.temp_keypad[i]       = KeyState::Unpressed}
-        for i in 0..(64 * 32) {self.screen[i]            = PixelState::Unlit}
+        for i in 0..16        {self.raw_keypad[i]        = KeyState::Unpressed}
+        for i in 0..16        {self.audio_pattern[i]     = 0x00}
+        self.audio_pitch = 64;
+        self.reset_display_mode();
+        if self.start_hires
+        {
+            self.resolution = Resolution::High;
+        }
 
         //Because the device's entire memory has been cleard, we must reload the default fontset.
         self.load_default_font();
+    }
+
+    /// Returns the display to the 64x32 low resolution mode and clears both display planes.
+    pub fn reset_display_mode(&mut self)
+    {
+        self.resolution = Resolution::Low;
+        self.screen       = [0; MAX_SCREEN_HEIGHT];
+        self.second_plane = [0; MAX_SCREEN_HEIGHT];
+    }
+
+    /// Returns the current display resolution.
+    pub fn resolution(&self) -> Resolution
+    {
+        self.resolution
+    }
+
+    /// Returns true if the display is in the Super-CHIP 128x64 high resolution mode.
+    pub fn is_hires(&self) -> bool
+    {
+        self.resolution == Resolution::High
+    }
+
+    /// Returns the width of the display, in pixels, for the current resolution.
+    pub fn screen_width(&self) -> u8
+    {
+        match self.resolution
+        {
+            Resolution::Low  => 64,
+            Resolution::High => 128
+        }
+    }
+
+    /// Returns the height of the display, in pixels, for the current resolution.
+    pub fn screen_height(&self) -> u8
+    {
+        match self.resolution
+        {
+            Resolution::Low  => 32,
+            Resolution::High => 64
+        }
+    }
+
+    // Returns the number of pixels shown at the current resolution.
+    fn screen_size(&self) -> usize
+    {
+        (self.screen_width() as usize) * (self.screen_height() as usize)
+    }
 
-        return;
+    // Each display plane is stored as one u128 per row, with the leftmost column in the most significant bit. Only the top
+    // screen_width() bits of the first screen_height() rows are shown; the rest are always unlit. The rows are u128 rather than u64
+    // so that a whole row of the 128x64 Super-CHIP display is still one word, and DRW can XOR a sprite row into it in one go.
+    fn column_bit(col: usize) -> u128
+    {
+        1 << (127 - col)
     }
 
     fn save_keypad(&mut self)
@@ -121,30 +665,27 @@ impl Chip8
         {
             self.temp_keypad[i] = self.keypad[i];
         }
-
-        return
     }
 
-    fn check_for_new_key_pressed(&mut self)
+    // Returns the program counter increment for the waiting LD Vx, K instruction. The PC stays put until a new key is pressed.
+    fn check_for_new_key_pressed(&mut self) -> pc_Increment
     {
         for i in 0..16
         {
             if (self.temp_keypad[i] == KeyState::Unpressed) && (self.keypad[i] == KeyState::Pressed)
             {
                 self.device_state = CpuState::Ready;
-                self.opcode_LD_VX_K_CONT(self.temp_vx, i as u8);
-
-                break;
+                return self.opcode_LD_VX_K_CONT(self.temp_vx, i as u8);
             }
         }
 
-        return;
+        pc_Increment::Jump
     }
 
-    ///! For setting a single byte of the device's memory. (don't forget to include a 0x200 byte offset for program data)
+    /// For setting a single byte of the device's memory. (don't forget to include a 0x200 byte offset for program data)
     pub fn set_memory_byte(&mut self, address: u16, byte: u8) -> bool
     {
-        if address < 4096
+        if (address as usize) < self.memory_size()
         {
             self.memory[address as usize] = byte;
             true
@@ -155,10 +696,10 @@ impl Chip8
         }
     }
 
-    ///! For setting a single word (2 bytes) of the device's memory. (don't forget to include a 0x200 byte offset for program data)
+    /// For setting a single word (2 bytes) of the device's memory. (don't forget to include a 0x200 byte offset for program data)
     pub fn set_memory_word(&mut self, address: u16, word: u16) -> bool
     {
-        if address < 4096
+        if (address as usize) + 1 < self.memory_size()
         {
             self.memory[address as usize] = (word >> 8) as u8;
             self.memory[(address + 1) as usize] = (word & 0xFF) as u8;
@@ -170,12 +711,14 @@ impl Chip8
         }
     }
 
-    ///! Sets the devices key to the desired state.
-    pub fn set_key(&mut self, key_number: u8, desired_state: KeyState) -> bool
+    /// For copying a block of bytes into the device's memory, such as graphics data kept apart from the program. Returns false,
+    /// leaving memory untouched, if the block would run past the end of memory.
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> bool
     {
-        if key_number < 16
+        let start: usize = address as usize;
+        if start + bytes.len() <= self.memory_size()
         {
-            self.keypad[key_number as usize] = desired_state;
+            self.memory[start..(start + bytes.len())].copy_from_slice(bytes);
             true
         }
         else
@@ -184,657 +727,3925 @@ impl Chip8
         }
     }
 
-    ///! Returns the state of the pixel at the indicated row and column.
-    pub fn get_screen_pixel(&mut self, row: u8, col: u8) -> Option<PixelState>
+    /// Copies the program into memory starting at PROGRAM_BASE (0x200). Memory is left untouched if the program doesn't fit.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), LoadError>
     {
-        if (row  < 32) && (col < 64)
-        {
-            let pixel: u16 = (64 * row as u16) + col as u16;
-            match self.screen[pixel as usize]
-            {
-                PixelState::Lit   => Some(PixelState::Lit),
-                PixelState::Unlit => Some(PixelState::Unlit)
-            }
-        }
-        else
+        let start: usize = PROGRAM_BASE as usize;
+        if start + rom.len() > self.memory_size()
         {
-            None
+            return Err(LoadError::TooLarge { len: rom.len() });
         }
+
+        self.memory[start..(start + rom.len())].copy_from_slice(rom);
+        self.program_len = rom.len();
+        Ok(())
     }
 
-    ///! Set the pixel to the desired state at the indicated row and column. Returns true if good, false if else.
-    pub fn set_screen_pixel(&mut self, row: u8, col: u8, desired_state: PixelState) -> bool
+    /// Writes a font to memory at base_addr, and makes Fx29 point I into it from then on. Fx29 still expects five bytes per glyph,
+    /// so extra glyphs, such as the Super-CHIP's 10-byte ones, can be stored after the first 16 and pointed at with LD I. The
+    /// default font is restored at FONT_BASE by a hard reset.
+    pub fn load_font(&mut self, font: &[u8], base_addr: u16) -> Result<(), LoadError>
     {
-        if (row  < 32) && (col < 64)
+        let start: usize = base_addr as usize;
+        if start + font.len() > self.memory_size()
         {
-            self.screen[((64 * row as u16) + col as u16) as usize] = desired_state;
-            return true;
+            return Err(LoadError::TooLarge { len: font.len() });
         }
 
-        false
+        self.memory[start..(start + font.len())].copy_from_slice(font);
+        self.font_base = base_addr;
+        Ok(())
     }
 
-    ///! Sets the program counter to the indicated byte. Returns true if the program counter was able to be set correctly, false if else.
-    pub fn set_program_counter(&mut self, desired_pc_value: u16) -> bool
+    /// Returns the CRC-32 (IEEE) of the program last loaded with load_rom, as read from memory now. Returns the CRC of an
+    /// empty program if nothing has been loaded since the last hard reset.
+    pub fn program_crc32(&self) -> u32
     {
-        if desired_pc_value < 4096
+        let start: usize = PROGRAM_BASE as usize;
+        let mut crc: u32 = 0xFFFFFFFF;
+        for byte in self.memory[start..(start + self.program_len)].iter()
         {
-            self.program_counter = desired_pc_value;
-            return true;
+            crc ^= *byte as u32;
+            for _ in 0..8
+            {
+                crc = if (crc & 1) != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            }
         }
 
-        false
+        !crc
     }
 
-    ///! Subtracts the indicated value from the delay counter.
-    pub fn subtract_from_delaycounter(&mut self, value_to_subtract: f32)
+    /// Looks up the loaded program's CRC-32 in the table of known ROMs and, if found, applies the matching profile's quirks.
+    /// Returns the profile that was applied, or None (leaving the quirks untouched) if the program isn't known.
+    pub fn auto_configure_quirks(&mut self) -> Option<QuirkProfile>
     {
-        self.timer_delay -= value_to_subtract;
-        if self.timer_delay < 0.0
-        {
-            self.timer_delay = 0.0;
-        }
+        self.auto_configure_quirks_from(KNOWN_ROM_PROFILES)
     }
 
-     ///! Subtracts the indicated value from the buzzer counter. Returns true if the buzzer counter has run out.
-    pub fn subtract_from_buzzercounter(&mut self, value_to_subtract: f32) -> bool
+    /// Like auto_configure_quirks, but looks the loaded program's CRC-32 up in the provided table of (CRC-32, profile) pairs, for
+    /// frontends that keep their own database of ROMs.
+    pub fn auto_configure_quirks_from(&mut self, table: &[(u32, QuirkProfile)]) -> Option<QuirkProfile>
     {
-        self.buzzer_delay -= value_to_subtract;
-        if self.buzzer_delay <= 0.0
-        {
-            self.buzzer_delay = 0.0;
-            return true;
-        }
+        let crc: u32 = self.program_crc32();
+        let profile: QuirkProfile = table.iter().find(|(known_crc, _)| *known_crc == crc)?.1;
+        self.quirks = profile.quirks();
 
-        false
+        Some(profile)
     }
 
-    // Loads the default font into 0x000.
-    fn load_default_font(&mut self)
+    /// Returns a copy of the device's current state, which can later be passed to restore.
+    pub fn snapshot(&self) -> Chip8Snapshot
     {
-        let font_set: [u8; 80] = 
-        [
-            0xF0, 0x90, 0x90, 0x90, 0xF0,		// 0
-	        0x20, 0x60, 0x20, 0x20, 0x70,		// 1
-	        0xF0, 0x10, 0xF0, 0x80, 0xF0,		// 2
-	        0xF0, 0x10, 0xF0, 0x10, 0xF0,		// 3
-    	    0x90, 0x90, 0xF0, 0x10, 0x10,		// 4
-    	    0xF0, 0x80, 0xF0, 0x10, 0xF0,		// 5
-    	    0xF0, 0x80, 0xF0, 0x90, 0xF0,		// 6
-    	    0xF0, 0x10, 0x20, 0x40, 0x40,		// 7
-    	    0xF0, 0x90, 0xF0, 0x90, 0xF0,		// 8
-    	    0xF0, 0x90, 0xF0, 0x10, 0xF0,		// 9
-	        0xF0, 0x90, 0xF0, 0x90, 0x90,		// A
-    	    0xE0, 0x90, 0xE0, 0x90, 0xE0,		// B
-    	    0xF0, 0x80, 0x80, 0x80, 0xF0,		// C
-    	    0xE0, 0x90, 0x90, 0x90, 0xE0,		// D
-    	    0xF0, 0x80, 0xF0, 0x80, 0xF0,		// E
-    	    0xF0, 0x80, 0xF0, 0x80, 0x80		// F
-        ];
-
-        //Load copy fontset into the devices memory
-        for i in 0..80
+        Chip8Snapshot
         {
-            self.set_memory_byte(i, font_set[i as usize]);
+            device_state:      self.device_state,
+            opcode:            self.opcode,
+            index:             self.index,
+            program_counter:   self.program_counter,
+            timer_delay:       self.timer_delay,
+            buzzer_delay:      self.buzzer_delay,
+            stack_pointer:     self.stack_pointer,
+            temp_vx:           self.temp_vx,
+            stack:             self.stack,
+            general_registers: self.general_registers,
+            memory:            self.memory[..self.memory_size()].to_vec(),
+            keypad:            self.keypad,
+            temp_keypad:       self.temp_keypad,
+            raw_keypad:        self.raw_keypad,
+            key_bounce_ticks:  self.key_bounce_ticks,
+            screen:            self.screen.to_vec(),
+            second_plane:      self.second_plane.to_vec(),
+            resolution:        self.resolution,
+            selected_planes:   self.selected_planes,
+            audio_pattern:     self.audio_pattern,
+            audio_pitch:       self.audio_pitch,
+            instructions_since_draw: self.instructions_since_draw,
+            collisions_this_frame: self.collisions_this_frame,
+            instructions_executed: self.instructions_executed,
+            timer_ticks:       self.timer_ticks,
+            index_from_font:   self.index_from_font,
+            last_instruction_skipped: self.last_instruction_skipped,
+            last_key_wait:     self.last_key_wait,
+            last_vf_source:    self.last_vf_source,
+            last_sprite:       self.last_sprite.clone(),
+            rng:               self.rng.clone()
         }
     }
 
-    ///! Fully executes one instruction. Automatically increments the program counter as needed.
-    pub fn execute(&mut self)
+    /// Returns the device to a state previously returned by snapshot, including the state of the random number generator used by RND.
+    pub fn restore(&mut self, snapshot: &Chip8Snapshot)
     {
-        let mut pc_op: pc_Increment = pc_Increment::Jump;
-        match self.device_state
-        {
-            CpuState::WaitingForKeypress => { self.check_for_new_key_pressed(); },
-            CpuState::Ready                => {
-                //Split the 16-byte opcode into four 4-bit nibbles. This will allow us to use pattern matching to detect the opcode.
-                let nibble3: u8 = (self.memory[ self.program_counter as usize]      & 0xF0) >> 4;
-                let nibble2: u8 =  self.memory[ self.program_counter as usize]      & 0x0F;
-                let nibble1: u8 = (self.memory[(self.program_counter as usize) + 1] & 0xF0) >> 4;
-                let nibble0: u8 =  self.memory[(self.program_counter as usize) + 1] & 0x0F;
-
-                //Decode the current instruction then execute the instruction.
-                pc_op = match (nibble3, nibble2, nibble1, nibble0)
-                {
-                    (0x0, 0x0, 0xE, 0x0) => self.opcode_CLS       (), //t
-                    (0x0, 0x0, 0xE, 0xE) => self.opcode_RET       (), //t
-                    (0x0,   _,   _,   _) => self.opcode_SYS       (), //t
-                    (0x1,   _,   _,   _) => self.opcode_JP        (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x2,   _,   _,   _) => self.opcode_CALL      (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x3,   _,   _,   _) => self.opcode_SE_VX     (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x4,   _,   _,   _) => self.opcode_SNE_VX    (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x5,   _,   _, 0x0) => self.opcode_SE_VX_VY  (nibble2, nibble1), //t
-                    (0x6,   _,   _,   _) => self.opcode_LD_VX     (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x7,   _,   _,   _) => self.opcode_ADD_VX    (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0x8,   _,   _, 0x0) => self.opcode_LD_VX_VY  (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x1) => self.opcode_OR_VX_VY  (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x2) => self.opcode_AND_VX_VY (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x3) => self.opcode_XOR_VX_VY (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x4) => self.opcode_ADD_VX_VY (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x5) => self.opcode_SUB_VX_VY (nibble2, nibble1), //t
-                    (0x8,   _,   _, 0x6) => self.opcode_SHR_VX    (nibble2),          //t
-                    (0x8,   _,   _, 0x7) => self.opcode_SUBN_VX_VY(nibble2, nibble1), //t
-                    (0x8,   _,   _, 0xE) => self.opcode_SHL_VX    (nibble2),          //t
-                    (0x9,   _,   _, 0x0) => self.opcode_SNE_VX_VY (nibble2, nibble1), //t
-                    (0xA,   _,   _,   _) => self.opcode_LD_I      (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0xB,   _,   _,   _) => self.opcode_JP_V0     (nibble2, (nibble1 << 4) | nibble0), //t
-                    (0xC,   _,   _,   _) => self.opcode_RND_VX    (nibble2, (nibble1 << 4) | nibble0), //Not working as intended
-                    (0xD,   _,   _,   _) => self.opcode_DRW_VX_VY (nibble2, nibble1, nibble0), //t
-                    (0xE,   _, 0x9, 0xE) => self.opcode_SKP_VX    (nibble2), //t
-                    (0xE,   _, 0xA, 0x1) => self.opcode_SKNP_VX   (nibble2),
-                    (0xF,   _, 0x0, 0x7) => self.opcode_LD_VX_DT  (nibble2),
-                    (0xF,   _, 0x0, 0xA) => self.opcode_LD_VX_K   (nibble2), //t
-                    (0xF,   _, 0x1, 0x5) => self.opcode_LD_DT_VX  (nibble2),
-                    (0xF,   _, 0x1, 0x8) => self.opcode_LD_ST_VX  (nibble2),
-                    (0xF,   _, 0x1, 0xE) => self.opcode_ADD_I_VX  (nibble2), //t
-                    (0xF,   _, 0x2, 0x9) => self.opcode_LD_F_VX   (nibble2), //t
-                    (0xF,   _, 0x3, 0x3) => self.opcode_LD_B_VX   (nibble2),
-                    (0xF,   _, 0x5, 0x5) => self.opcode_LD_iIi_VX (nibble2),
-                    (0xF,   _, 0x6, 0x5) => self.opcode_LD_VX_iIi (nibble2),
-                    (  _,   _,   _,   _) => (pc_Increment::Next)
-                }
-            }
-        }
-
-        //Increment the program counter as previously indicated by the instruction
-        match pc_op
-        {
-            pc_Increment::Next => self. program_counter = (self.program_counter + 2) % 4096,
-            pc_Increment::Skip => self. program_counter = (self.program_counter + 4) % 4096,
-            _ => ()
-        }
+        self.device_state      = snapshot.device_state;
+        self.opcode            = snapshot.opcode;
+        self.index             = snapshot.index;
+        self.program_counter   = snapshot.program_counter;
+        self.timer_delay       = snapshot.timer_delay;
+        self.buzzer_delay      = snapshot.buzzer_delay;
+        self.stack_pointer     = snapshot.stack_pointer;
+        self.temp_vx           = snapshot.temp_vx;
+        self.stack             = snapshot.stack;
+        self.general_registers = snapshot.general_registers;
+        self.memory[..snapshot.memory.len()].copy_from_slice(&snapshot.memory);
+        self.keypad            = snapshot.keypad;
+        self.temp_keypad       = snapshot.temp_keypad;
+        self.raw_keypad        = snapshot.raw_keypad;
+        self.key_bounce_ticks  = snapshot.key_bounce_ticks;
+        self.screen.copy_from_slice(&snapshot.screen);
+        self.second_plane.copy_from_slice(&snapshot.second_plane);
+        self.resolution        = snapshot.resolution;
+        self.selected_planes   = snapshot.selected_planes;
+        self.audio_pattern     = snapshot.audio_pattern;
+        self.audio_pitch       = snapshot.audio_pitch;
+        self.instructions_since_draw = snapshot.instructions_since_draw;
+        self.collisions_this_frame   = snapshot.collisions_this_frame;
+        self.instructions_executed = snapshot.instructions_executed;
+        self.timer_ticks       = snapshot.timer_ticks;
+        self.index_from_font   = snapshot.index_from_font;
+        self.last_instruction_skipped = snapshot.last_instruction_skipped;
+        self.last_key_wait     = snapshot.last_key_wait;
+        self.last_vf_source    = snapshot.last_vf_source;
+        self.last_sprite       = snapshot.last_sprite.clone();
+        self.rng               = snapshot.rng.clone();
+        self.seek_origin       = None;
+        self.key_log.clear();
     }
 
-    //Function for execution of CLS opcode. Clears the screen.
-    #[allow(non_snake_case)]
-    fn opcode_CLS(&mut self) -> pc_Increment
+    /// Returns a hash of the device's state (everything captured by snapshot), for quickly checking whether two devices, or one device
+    /// at two points in time, are in the same state. Hashes are only comparable between builds of the same version of this crate.
+    pub fn state_hash(&self) -> u64
     {
-        for i in 0..(64 * 32) 
-        {
-            self.screen[i] = PixelState::Unlit
-        }
+        let mut hasher: DefaultHasher = DefaultHasher::new();
+        self.device_state.hash(&mut hasher);
+        self.opcode.hash(&mut hasher);
+        self.index.hash(&mut hasher);
+        self.program_counter.hash(&mut hasher);
+        self.timer_delay.hash(&mut hasher);
+        self.buzzer_delay.hash(&mut hasher);
+        self.stack_pointer.hash(&mut hasher);
+        self.temp_vx.hash(&mut hasher);
+        self.stack.hash(&mut hasher);
+        self.general_registers.hash(&mut hasher);
+        self.memory[..self.memory_size()].hash(&mut hasher);
+        self.keypad.hash(&mut hasher);
+        self.temp_keypad.hash(&mut hasher);
+        self.raw_keypad.hash(&mut hasher);
+        self.key_bounce_ticks.hash(&mut hasher);
+        self.screen.hash(&mut hasher);
+        self.second_plane.hash(&mut hasher);
+        self.resolution.hash(&mut hasher);
+        self.selected_planes.hash(&mut hasher);
+        self.audio_pattern.hash(&mut hasher);
+        self.audio_pitch.hash(&mut hasher);
+        self.instructions_since_draw.hash(&mut hasher);
+        self.collisions_this_frame.hash(&mut hasher);
+        self.instructions_executed.hash(&mut hasher);
+        self.timer_ticks.hash(&mut hasher);
+        self.index_from_font.hash(&mut hasher);
+        self.last_instruction_skipped.hash(&mut hasher);
+        self.last_key_wait.hash(&mut hasher);
+        self.last_vf_source.hash(&mut hasher);
+        self.last_sprite.hash(&mut hasher);
+        //Pcg64 doesn't implement Hash, so the next number it will produce stands in for its state.
+        self.rng.clone().next_u64().hash(&mut hasher);
 
-        pc_Increment::Next
+        hasher.finish()
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_SYS(&mut self) -> pc_Increment
+    /// Returns the most recently fetched instruction. (0 if no instruction has been executed since the last reset)
+    pub fn current_opcode(&self) -> u16
     {
-        pc_Increment::Next
+        self.opcode
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_RET(&mut self) -> pc_Increment
+    /// Returns the number of instructions executed since the last reset. Every instruction counts once, including Fx0A, which isn't
+    /// counted again while it waits for a keypress.
+    pub fn instructions_executed(&self) -> u64
     {
-        self.program_counter = self.stack[self.stack_pointer as usize] + 2;
+        self.instructions_executed
+    }
 
-        if self.stack_pointer == 0
+    /// Runs or rewinds the device to the point where exactly target instructions have been executed since the last reset.
+    ///
+    /// Seeking backwards restores the state from just before the first instruction executed since the last reset or restore, then
+    /// replays from there, repeating the key changes made with set_key at the same points. Because RND uses the device's own seeded
+    /// generator, the replay is exact. Seeking stops early if the device faults or is left waiting for a keypress that never came,
+    /// and can't go back further than where the replay starts.
+    ///
+    /// To make the replay possible, every key change since the last reset or restore is kept, so the memory this takes grows with
+    /// the number of key presses and releases until the device is next reset or restored.
+    pub fn seek_to_instruction(&mut self, target: u64)
+    {
+        if target < self.instructions_executed
         {
-            self.stack_pointer = 0xF;
+            let origin: Box<Chip8Snapshot> = match self.seek_origin.take()
+            {
+                Some(origin) => origin,
+                None         => return
+            };
+            let key_log: Vec<(u64, u8, KeyState)> = std::mem::take(&mut self.key_log);
+
+            self.restore(&origin);
+            self.seek_origin = Some(origin);
+            self.key_log     = key_log;
         }
-        else
+
+        while self.instructions_executed < target
         {
-            self.stack_pointer -= 1;
-        }
+            let executed: u64 = self.instructions_executed;
+            for (_, key_number, desired_state) in self.key_log.iter().filter(|(logged, _, _)| *logged == executed)
+            {
+                self.keypad[*key_number as usize] = *desired_state;
+            }
 
-        pc_Increment::Jump
+            match self.device_state
+            {
+                CpuState::WaitingForKeypress => {
+                    self.execute();
+                    if self.device_state == CpuState::WaitingForKeypress
+                    {
+                        break;
+                    }
+                },
+                CpuState::Faulted(_) | CpuState::Halted => break,
+                CpuState::Ready      => ()
+            }
+
+            self.execute();
+        }
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_JP(&mut self, n: u8, nn: u8) -> pc_Increment
+    /// Seeds the random number generator used by RND, making the values it produces reproducible. Any generator set with set_rng
+    /// is removed.
+    pub fn seed_rng(&mut self, seed: u64)
     {
-        self.program_counter = ((n as u16) << 8) | (nn as u16);  
+        self.rng        = Pcg64::seed_from_u64(seed);
+        self.custom_rng = None;
+    }
 
-        pc_Increment::Jump
+    /// Replaces the random number generator used by RND with the provided one, until it is removed by seed_rng. Its state isn't
+    /// part of a snapshot, so restoring a snapshot doesn't rewind it.
+    pub fn set_rng(&mut self, rng: Box<dyn Chip8Rng>)
+    {
+        self.custom_rng = Some(rng);
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_CALL(&mut self, n: u8, nn: u8) -> pc_Increment
+    /// Returns the indicated range of memory as Intel HEX data records (16 bytes per record) followed by an end-of-file record.
+    /// Each record is terminated with a newline. The range is cut short at the end of memory.
+    pub fn to_intel_hex(&self, start: u16, len: u16) -> String
     {
-        //println!("JP: {} \t{}", n, nn);
-        self.stack_pointer += 1;
-        if self.stack_pointer > 0xF
+        let start: usize = (start as usize).min(self.memory_size());
+        let end:   usize = (start + (len as usize)).min(self.memory_size());
+        let mut hex: String = String::new();
+
+        for (record_number, record) in self.memory[start..end].chunks(16).enumerate()
         {
-            self.stack_pointer = 0;
+            let address: usize = start + (record_number * 16);
+            let mut checksum: u8 = (record.len() as u8).wrapping_add((address >> 8) as u8).wrapping_add(address as u8);
+
+            hex.push_str(&format!(":{:02X}{:04X}00", record.len(), address));
+            for byte in record
+            {
+                hex.push_str(&format!("{:02X}", byte));
+                checksum = checksum.wrapping_add(*byte);
+            }
+            hex.push_str(&format!("{:02X}\n", checksum.wrapping_neg()));
         }
-        self.stack[self.stack_pointer as usize] = self.program_counter;
-        self.program_counter = ((n as u16) << 8) | (nn as u16);
 
-        pc_Increment::Jump
+        hex.push_str(":00000001FF\n");
+        hex
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SE_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    /// Sets the quirks used when executing instructions.
+    pub fn set_quirks(&mut self, quirks: Quirks)
     {
-        if kk == self.general_registers[vx as usize]
-        {
-            pc_Increment::Jump // Skip the next instruction
-        }
-        else
-        {
-            pc_Increment::Next
-        }
+        self.quirks = quirks;
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SNE_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    /// Returns the quirks currently in use.
+    pub fn quirks(&self) -> Quirks
     {
-        if kk != self.general_registers[vx as usize]
-        {
-            pc_Increment::Jump // Skip the next instruction
-        }
-        else
-        {
-            pc_Increment::Next
-        }
+        self.quirks
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SE_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Returns true if the quirks in use match Quirks::default(), false if any quirk has been customized.
+    pub fn is_default_quirks(&self) -> bool
     {
-        if self.general_registers[vx as usize] == self.general_registers[vy as usize]
-        {
-            pc_Increment::Jump // Skip the next instruction
-        }
-        else
-        {
-            pc_Increment::Next
-        }
+        self.quirks == Quirks::default()
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    /// Enables or disables strict memory checking. While enabled, suspicious memory accesses are reported through the warning hook.
+    ///
+    /// Currently this only checks DRW: a sprite read from the font region (FONT_SIZE bytes from the font's base) is reported as Warning::SpriteFromFontRegion
+    /// unless I was last set by Fx29. Any other change to I, including ADD I, Vx and the draw_advances_i quirk, counts as I no longer
+    /// being set by Fx29. This is a heuristic; ROMs that deliberately point I at font data with LD I will also trigger it.
+    pub fn set_strict_memory(&mut self, enabled: bool)
     {
-        self.general_registers[vx as usize] = kk;
-
-        pc_Increment::Next
+        self.strict_memory = enabled;
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_ADD_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    /// Sets the function called whenever the device raises a warning.
+    pub fn set_warning_hook(&mut self, hook: Box<dyn FnMut(Warning)>)
     {
-        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_add(kk);
-
-        pc_Increment::Next
+        self.warning_hook = Some(hook);
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Removes the warning hook, if one was set.
+    pub fn clear_warning_hook(&mut self)
     {
-        self.general_registers[vx as usize] = self.general_registers[vy as usize];
+        self.warning_hook = None;
+    }
 
-        pc_Increment::Next
+    fn warn(&mut self, warning: Warning)
+    {
+        if let Some(hook) = self.warning_hook.as_mut()
+        {
+            hook(warning);
+        }
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_OR_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Exchanges the values of registers x and y. No other registers (including VF) are affected.
+    pub fn swap_registers(&mut self, x: u8, y: u8) -> Result<(), RegError>
     {
-        self.general_registers[vx as usize] |= self.general_registers[vy as usize];
+        if x > 0xF
+        {
+            return Err(RegError::InvalidRegister(x));
+        }
+        if y > 0xF
+        {
+            return Err(RegError::InvalidRegister(y));
+        }
 
-        pc_Increment::Next
+        self.general_registers.swap(x as usize, y as usize);
+        Ok(())
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_AND_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Starts recording the last depth values held by register n. The register's current value is recorded immediately, then a new
+    /// value is recorded after each instruction that changes it. Watching a register again restarts its history with the new depth,
+    /// and a depth of 0 stops watching it. Register numbers above 0xF are ignored.
+    pub fn watch_register(&mut self, n: u8, depth: usize)
     {
-        self.general_registers[vx as usize] &= self.general_registers[vy as usize];
+        if n > 0xF
+        {
+            return;
+        }
 
-        pc_Increment::Next
+        self.register_watches.retain(|watch| watch.register != n);
+        if depth > 0
+        {
+            let history: Vec<u8> = vec![self.general_registers[n as usize]];
+            self.register_watches.push(RegisterWatch { register: n, depth, history });
+        }
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_XOR_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Returns the values recorded for register n, oldest first, or None if the register isn't being watched.
+    pub fn register_history(&self, n: u8) -> Option<&[u8]>
     {
-        self.general_registers[vx as usize] ^= self.general_registers[vy as usize];
+        self.register_watches.iter().find(|watch| watch.register == n).map(|watch| watch.history.as_slice())
+    }
 
-        pc_Increment::Next
+    /// Returns the mnemonics of the kinds of instructions executed since the last reset, in the order they were first executed.
+    /// Opcodes that aren't part of any instruction are reported as "?".
+    pub fn executed_opcode_kinds(&self) -> Vec<&'static str>
+    {
+        self.executed_opcode_kinds.clone()
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_ADD_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    fn record_opcode_kind(&mut self)
     {
-        if (self.general_registers[vx as usize] as u16) + (self.general_registers[vy as usize] as u16) > 0xFF
+        let kind: &'static str = decode(self.opcode).mnemonic();
+        if !self.executed_opcode_kinds.contains(&kind)
         {
-            self.general_registers[0xF] = 1;
+            self.executed_opcode_kinds.push(kind);
         }
-        else
+    }
+
+    fn record_register_watches(&mut self)
+    {
+        for watch in self.register_watches.iter_mut()
         {
-            self.general_registers[0xF] = 0;
+            let value: u8 = self.general_registers[watch.register as usize];
+            if watch.history.last() != Some(&value)
+            {
+                if watch.history.len() == watch.depth
+                {
+                    watch.history.remove(0);
+                }
+                watch.history.push(value);
+            }
         }
-        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_sub(self.general_registers[vy as usize]);
-
-        pc_Increment::Next
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SUB_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Sets the devices key to the desired state. With debouncing enabled (see set_key_debounce), the keypad only changes once the
+    /// key has been held in the new state for the debounce period.
+    pub fn set_key(&mut self, key_number: u8, desired_state: KeyState) -> bool
     {
-        if (self.general_registers[vx as usize] as i16) - (self.general_registers[vy as usize] as i16) < 0
+        if key_number < 16
         {
-            self.general_registers[0xF] = 0;
+            if self.key_debounce == 0
+            {
+                self.apply_key(key_number, desired_state);
+            }
+            else if self.raw_keypad[key_number as usize] != desired_state
+            {
+                self.key_bounce_ticks[key_number as usize] = 0;
+            }
+            self.raw_keypad[key_number as usize] = desired_state;
+            true
         }
         else
         {
-            self.general_registers[0xF] = 1;
+            false
         }
+    }
 
-        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_sub(self.general_registers[vy as usize]);
-        
-        pc_Increment::Next
+    /// Returns the key changes, as (key, new state), made to the keypad since the last call, oldest first. Calls that leave a key in
+    /// the state it was already in aren't recorded.
+    pub fn drain_key_events(&mut self) -> Vec<(u8, KeyState)>
+    {
+        std::mem::take(&mut self.key_events)
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SHR_VX(&mut self, vx: u8) -> pc_Increment
+    /// Sets the number of 60Hz timer ticks (see tick_timers) a key must stay in a new state, as set with set_key, before the keypad
+    /// changes. Changes that are undone sooner, such as the chatter of a mechanical switch, are ignored. 0 disables debouncing, which
+    /// applies any changes still waiting out the period.
+    pub fn set_key_debounce(&mut self, ticks: u16)
     {
-        if (self.general_registers[vx as usize] & 1) != 0
+        self.key_debounce = ticks;
+        if ticks == 0
         {
-            self.general_registers[0xF] = 1;
+            for key_number in 0..16
+            {
+                self.apply_key(key_number, self.raw_keypad[key_number as usize]);
+            }
         }
-        else
+    }
+
+    // Changes the state of a key on the keypad, logging the change so that seek_to_instruction can replay it.
+    fn apply_key(&mut self, key_number: u8, desired_state: KeyState)
+    {
+        if self.keypad[key_number as usize] != desired_state
         {
-            self.general_registers[0xF] = 0;
+            self.key_log.push((self.instructions_executed, key_number, desired_state));
+            self.key_events.push((key_number, desired_state));
         }
-        self.general_registers[vx as usize] >>= 1;
-
-        pc_Increment::Next
+        self.keypad[key_number as usize] = desired_state;
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SUBN_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    // Counts one tick towards the debounce period of every key whose state is waiting to change, changing those that reach it.
+    fn debounce_keys(&mut self)
     {
-        if (self.general_registers[vy as usize] as i16) - (self.general_registers[vx as usize] as i16) < 0
+        if self.key_debounce == 0
         {
-            self.general_registers[0xF] = 0;
+            return;
         }
-        else
+
+        for key_number in 0..16
         {
-            self.general_registers[0xF] = 1;
+            let raw_state: KeyState = self.raw_keypad[key_number as usize];
+            if raw_state != self.keypad[key_number as usize]
+            {
+                self.key_bounce_ticks[key_number as usize] += 1;
+                if self.key_bounce_ticks[key_number as usize] >= self.key_debounce
+                {
+                    self.apply_key(key_number, raw_state);
+                }
+            }
         }
-        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_sub(self.general_registers[vy as usize]);
+    }
 
-        pc_Increment::Next
+    /// Returns the label of the indicated key, or None if there is no such key. Labels default to the key's hex digit.
+    pub fn key_label(&self, key_number: u8) -> Option<&str>
+    {
+        self.key_labels.get(key_number as usize).map(|label| label.as_str())
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SHL_VX(&mut self, vx: u8) -> pc_Increment
+    /// Sets the label of the indicated key, for frontends to show in place of the key's hex digit. Returns true if good, false if else.
+    pub fn set_key_label(&mut self, key_number: u8, label: &str) -> bool
     {
-        if (self.general_registers[vx as usize] & 0b10000000) == 0
+        if key_number < 16
         {
-            self.general_registers[0xF] = 0;
+            self.key_labels[key_number as usize] = String::from(label);
+            true
         }
         else
         {
-            self.general_registers[0xF] = 1;
+            false
         }
-
-        self.general_registers[vx as usize] <<= 2;
-
-        pc_Increment::Next
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_SNE_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    /// Returns the state of the pixel at the indicated row and column.
+    pub fn get_screen_pixel(&mut self, row: u8, col: u8) -> Option<PixelState>
     {
-        if self.general_registers[vx as usize] != self.general_registers[vy as usize]
+        if (row < self.screen_height()) && (col < self.screen_width())
         {
-            pc_Increment::Skip // Skips next instruction
+            if (self.screen[row as usize] & Chip8::column_bit(col as usize)) != 0
+            {
+                Some(PixelState::Lit)
+            }
+            else
+            {
+                Some(PixelState::Unlit)
+            }
         }
         else
         {
-            pc_Increment::Next
+            None
         }
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_LD_I(&mut self, n: u8, nn: u8) -> pc_Increment
+    /// Set the pixel to the desired state at the indicated row and column. Returns true if good, false if else.
+    pub fn set_screen_pixel(&mut self, row: u8, col: u8, desired_state: PixelState) -> bool
     {
-        self.index = ((n as u16) << 8) | (nn as u16);
+        if (row < self.screen_height()) && (col < self.screen_width())
+        {
+            match desired_state
+            {
+                PixelState::Lit   => self.screen[row as usize] |=  Chip8::column_bit(col as usize),
+                PixelState::Unlit => self.screen[row as usize] &= !Chip8::column_bit(col as usize)
+            }
+            return true;
+        }
 
-        pc_Increment::Next
+        false
     }
 
-    #[allow(non_snake_case)]
-    fn opcode_JP_V0(&mut self, n: u8, nn: u8) -> pc_Increment
+    /// Returns the number of lit pixels on the screen.
+    pub fn lit_pixel_count(&self) -> usize
     {
-        self.program_counter = ((((n as u16) << 8) | (nn as u16)) + (self.general_registers[0] as u16)) % 4096;
-
-        pc_Increment::Jump
+        self.screen.iter().map(|row| row.count_ones() as usize).sum()
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_RND_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    /// Returns the smallest box containing every lit pixel, as (min_x, min_y, max_x, max_y) where x is the column and y is the
+    /// row, or None if no pixels are lit.
+    pub fn lit_bounds(&self) -> Option<(u8, u8, u8, u8)>
     {
-        let mut rng = rand::thread_rng();
-        self.general_registers[vx as usize] = kk & rng.gen::<u8>();
+        let mut bounds: Option<(u8, u8, u8, u8)> = None;
+        for (row, row_bits) in self.screen.iter().enumerate().filter(|(_, row_bits)| **row_bits != 0)
+        {
+            let row:       u8 = row as u8;
+            let first_col: u8 = row_bits.leading_zeros() as u8;
+            let last_col:  u8 = 127 - (row_bits.trailing_zeros() as u8);
+            bounds = Some(match bounds
+            {
+                Some((min_x, min_y, max_x, _)) => (min_x.min(first_col), min_y, max_x.max(last_col), row),
+                None                           => (first_col, row, last_col, row)
+            });
+        }
 
-        pc_Increment::Next
+        bounds
     }
 
-    //TODO: bounds check for general_registers, index, memory, sprite wrapping
-    #[allow(non_snake_case)]
-    fn opcode_DRW_VX_VY(&mut self, vx: u8, vy: u8, n: u8) -> pc_Increment
+    /// Returns the screen packed one bit per pixel, row-major, with the most significant bit of each byte being the leftmost pixel.
+    pub fn framebuffer_bits(&self) -> Vec<u8>
     {
-        let vx = vx % 64;
-        let vy = vy % 32;
-        let x_pos = self.general_registers[vx as usize];
-        let y_pos = self.general_registers[vy as usize];
-        self.general_registers[0xF] = 0;
-
-        for current_sprite_pixel_y in 0..n
+        let row_bytes: usize = (self.screen_width() as usize) / 8;
+        let mut bits: Vec<u8> = Vec::with_capacity(self.screen_size() / 8);
+        for row in self.screen[..(self.screen_height() as usize)].iter()
         {
-            for current_sprite_pixel_x in 0..8
-            {
-                let mut screen_pixel_x = (current_sprite_pixel_x as u16) + (x_pos as u16);
-                let mut screen_pixel_y = (current_sprite_pixel_y as u16) + (y_pos as u16);
-
-                if screen_pixel_x > 63
-                {
-                    screen_pixel_x = screen_pixel_x % 64;
-                }
-
-                if screen_pixel_y > 31
-                {
-                    screen_pixel_y = screen_pixel_y % 32;
-                }
-
-                let mut pixel: PixelState = PixelState::Unlit;
-                let pixel_bit: u8 = (0b10000000 >> current_sprite_pixel_x) & self.memory[((self.index as u16) + (current_sprite_pixel_y as u16)) as usize];
-                
-                if pixel_bit != 0
-                {
-                    pixel = PixelState::Lit;
-                }
-
-                let current_pixel: PixelState = self.screen[((screen_pixel_x as u16) + ((screen_pixel_y as u16) * 64)) as usize];
-                match (current_pixel, pixel)
-                {
-                    (PixelState::Unlit, PixelState::Lit)   =>  pixel = PixelState::Lit,
-                    (PixelState::Lit,   PixelState::Lit)   => {pixel = PixelState::Unlit; self.general_registers[0xF] = 1;},
-                    (PixelState::Unlit, PixelState::Unlit) =>  pixel = PixelState::Unlit,
-                    (PixelState::Lit,   PixelState::Unlit) =>  pixel = PixelState::Lit
-                }
-
-                self.screen[((screen_pixel_x as u16) + ((screen_pixel_y as u16) * 64)) as usize] = pixel;
-            }
+            bits.extend_from_slice(&row.to_be_bytes()[..row_bytes]);
         }
 
-        pc_Increment::Next
+        bits
     }
 
-    //TODO: bounds check for keypad
-    #[allow(non_snake_case)]
-    fn opcode_SKP_VX(&mut self, vx: u8) -> pc_Increment
+    /// Returns the 64x32 screen packed into 256 bytes, one bit per pixel. Rows are stored in order, eight bytes per row,
+    /// and the most significant bit of each byte is the leftmost of its eight pixels, so the pixel at (row, col) is bit
+    /// 7 - (col % 8) of byte row * 8 + col / 8. In high resolution only the top-left 64x32 region is returned.
+    pub fn framebuffer(&self) -> [u8; 256]
     {
-        if vx > 0xF
+        let row_bytes: usize = (self.screen_width() as usize) / 8;
+        let mut bits: [u8; 256] = [0; 256];
+        for (row, row_bits) in self.framebuffer_bits().chunks_exact(row_bytes).zip(bits.chunks_exact_mut(8))
         {
-            return pc_Increment::Next;
+            row_bits.copy_from_slice(&row[..8]);
         }
 
-        if self.keypad[self.general_registers[vx as usize] as usize] == KeyState::Pressed
-        {
-            pc_Increment::Skip
-        }
-        else
-        {
-            pc_Increment::Next
-        }
+        bits
     }
 
-    //TODO: bounds check for keypad
-    #[allow(non_snake_case)]
-    fn opcode_SKNP_VX(&mut self, vx: u8) -> pc_Increment
+    /// Sets the whole screen from bits packed as returned by framebuffer_bits. Returns false, leaving the screen untouched, if the
+    /// length of bits does not match the screen at the current resolution.
+    pub fn set_framebuffer_bits(&mut self, bits: &[u8]) -> bool
     {
-        if vx > 0xF
+        let row_bytes: usize = (self.screen_width() as usize) / 8;
+        if bits.len() != self.screen_size() / 8
         {
-            return pc_Increment::Next;
+            return false;
         }
 
-        if self.keypad[vx as usize] == KeyState::Unpressed
+        for (row, row_bits) in self.screen.iter_mut().zip(bits.chunks_exact(row_bytes))
         {
-            pc_Increment::Skip
+            let mut bytes: [u8; 16] = [0; 16];
+            bytes[..row_bytes].copy_from_slice(row_bits);
+            *row = u128::from_be_bytes(bytes);
         }
-        else
+
+        true
+    }
+
+    /// Returns the value of general register Vx, or None if x is greater than 0xF.
+    pub fn register(&self, x: u8) -> Option<u8>
+    {
+        self.general_registers.get(x as usize).copied()
+    }
+
+    /// Returns the value of the index register.
+    pub fn index(&self) -> u16
+    {
+        self.index
+    }
+
+    /// Returns the value of the program counter.
+    pub fn program_counter(&self) -> u16
+    {
+        self.program_counter
+    }
+
+    /// Returns the value of the stack pointer, which is the number of stack slots in use.
+    pub fn stack_pointer(&self) -> u8
+    {
+        self.stack_pointer
+    }
+
+    /// Returns the whole stack, including unused slots. Each used slot holds the address of a CALL. (see call_stack for return addresses)
+    pub fn stack(&self) -> &[u16; 16]
+    {
+        &self.stack
+    }
+
+    /// Returns the value of the index register. (Alias of index)
+    #[deprecated(note = "use index")]
+    pub fn get_index(&self) -> u16
+    {
+        self.index
+    }
+
+    /// Returns the value of the index register. (Alias of index using the usual Chip-8 name, I)
+    #[deprecated(note = "use index")]
+    pub fn i(&self) -> u16
+    {
+        self.index
+    }
+
+    /// Sets the index register. The value is masked to 12 bits, or 16 bits in XO-CHIP mode.
+    pub fn set_i(&mut self, value: u16)
+    {
+        let index_mask: u16 = if self.xo_chip { 0xFFFF } else { 0x0FFF };
+        self.index = value & index_mask;
+        self.index_from_font = false;
+    }
+
+    /// Enables or disables Super-CHIP mode. Super-CHIP adds the 128x64 high resolution mode, entered with 00FF and left with 00FE.
+    pub fn set_super_chip(&mut self, enabled: bool)
+    {
+        self.super_chip = enabled;
+    }
+
+    /// Returns true if Super-CHIP mode is enabled.
+    pub fn is_super_chip(&self) -> bool
+    {
+        self.super_chip
+    }
+
+    /// Enables or disables XO-CHIP mode. XO-CHIP adds a second display plane, selected with the Fn01 instruction, and extends memory
+    /// to 64KB. The extra memory is kept, but can't be reached, while XO-CHIP mode is disabled.
+    pub fn set_xo_chip(&mut self, enabled: bool)
+    {
+        self.xo_chip = enabled;
+        self.memory_mask = if enabled { (XO_CHIP_MEMORY_SIZE - 1) as u16 } else { (MEMORY_SIZE - 1) as u16 };
+    }
+
+    // Returns the number of bytes of memory that can be reached in the current mode.
+    fn memory_size(&self) -> usize
+    {
+        (self.memory_mask as usize) + 1
+    }
+
+    /// Returns true if XO-CHIP mode is enabled.
+    pub fn is_xo_chip(&self) -> bool
+    {
+        self.xo_chip
+    }
+
+    /// Returns the instruction set the device is running, as set with set_super_chip and set_xo_chip. XO-CHIP mode takes
+    /// precedence, as it includes the Super-CHIP instructions.
+    pub fn variant(&self) -> Variant
+    {
+        if self.xo_chip
         {
-            pc_Increment::Next
+            Variant::XoChip
+        }
+        else if self.super_chip
+        {
+            Variant::SuperChip
+        }
+        else
+        {
+            Variant::Chip8
         }
     }
 
-    //TODO: bounds check for keypad
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX_DT(&mut self, vx: u8) -> pc_Increment
+    /// Sets the RGBA colors used by render_rgba_xochip. Each pixel's two plane bits (first plane = bit 0, second plane = bit 1)
+    /// index into the palette.
+    pub fn set_palette(&mut self, colors: [[u8; 4]; 4])
     {
-        self.general_registers[vx as usize] = self.timer_delay.ceil() as u8;
+        self.palette = colors;
+    }
 
-        pc_Increment::Next
+    /// Renders both display planes to an RGBA buffer, row-major, using the palette set by set_palette.
+    pub fn render_rgba_xochip(&self) -> Vec<u8>
+    {
+        let mut rgba: Vec<u8> = Vec::with_capacity(self.screen_size() * 4);
+        for row in 0..(self.screen_height() as usize)
+        {
+            for col in 0..(self.screen_width() as usize)
+            {
+                let mut color_index: usize = 0;
+                if (self.screen[row]       & Chip8::column_bit(col)) != 0 {color_index |= 0b01}
+                if (self.second_plane[row] & Chip8::column_bit(col)) != 0 {color_index |= 0b10}
+
+                rgba.extend_from_slice(&self.palette[color_index]);
+            }
+        }
+
+        rgba
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX_K(&mut self, vx: u8) -> pc_Increment
+    /// Returns the XO-CHIP audio pattern: 128 one-bit samples, most significant bit first, played while the buzzer counter is running.
+    pub fn audio_pattern(&self) -> [u8; 16]
     {
-        self.temp_vx = vx;
-        self.device_state = CpuState::WaitingForKeypress;
-        self.save_keypad();
+        self.audio_pattern
+    }
 
-        pc_Increment::Jump
+    /// Returns the XO-CHIP pitch register. (64 is the default)
+    pub fn audio_pitch(&self) -> u8
+    {
+        self.audio_pitch
     }
 
-    //Executed after a keypress is performed
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX_K_CONT(&mut self, vx: u8, pressed_key: u8) -> pc_Increment
+    /// Returns the rate, in samples per second, that the audio pattern should be played at for the current pitch.
+    pub fn audio_sample_rate(&self) -> f32
     {
-        self.general_registers[vx as usize] = pressed_key;
+        4000.0 * 2.0_f32.powf(((self.audio_pitch as f32) - 64.0) / 48.0)
+    }
 
-        pc_Increment::Next
+    /// Returns the number of instructions executed since the screen was last changed by CLS or DRW.
+    pub fn instructions_since_draw(&self) -> u32
+    {
+        self.instructions_since_draw
     }
 
-    //TODO: bounds check for keypad
-    #[allow(non_snake_case)]
-    fn opcode_LD_DT_VX(&mut self, vx: u8) -> pc_Increment
+    /// Returns the number of DRW instructions that collided with lit pixels (setting VF to 1) during the current frame. The count
+    /// is reset at the start of every frame run by run_frame, so between frames it holds the count for the frame just run.
+    pub fn collisions_this_frame(&self) -> u32
     {
-        self.timer_delay = self.general_registers[vx as usize] as f32;
+        self.collisions_this_frame
+    }
 
-        pc_Increment::Next
+    /// Saves the screen to a PNG file, scaling each pixel up to a scale x scale square. Lit pixels are white and unlit pixels black.
+    /// (requires the "image" feature)
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: &str, scale: u32) -> image::ImageResult<()>
+    {
+        let scale: u32 = scale.max(1);
+        let width:  u32 = self.screen_width()  as u32;
+        let height: u32 = self.screen_height() as u32;
+        let png = image::GrayImage::from_fn(width * scale, height * scale, |x, y| {
+            if (self.screen[(y / scale) as usize] & Chip8::column_bit((x / scale) as usize)) != 0
+            {
+                image::Luma([0xFF])
+            }
+            else
+            {
+                image::Luma([0x00])
+            }
+        });
+
+        png.save(path)
     }
 
-    //TODO: bounds check for keypad
-    #[allow(non_snake_case)]
-    fn opcode_LD_ST_VX(&mut self, vx: u8) -> pc_Increment
+    /// Sets the program counter to the indicated byte. Returns true if the program counter was able to be set correctly, false if else.
+    pub fn set_program_counter(&mut self, desired_pc_value: u16) -> bool
     {
-        self.buzzer_delay = self.general_registers[vx as usize] as f32;
+        self.try_set_program_counter(desired_pc_value).is_ok()
+    }
 
-        pc_Increment::Next
+    /// Sets the program counter to the indicated byte, or returns an error, leaving the program counter untouched, if the address is
+    /// past the end of memory. (4096 bytes, or 65536 in XO-CHIP mode)
+    pub fn try_set_program_counter(&mut self, pc: u16) -> Result<(), AddressError>
+    {
+        if (pc as usize) >= self.memory_size()
+        {
+            return Err(AddressError::OutOfRange(pc));
+        }
+
+        self.program_counter = pc;
+        Ok(())
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_ADD_I_VX(&mut self, vx: u8) -> pc_Increment
+    /// Performs one 60Hz timer tick, subtracting 1 from both the delay and buzzer counters, stopping at 0. Returns true if the
+    /// buzzer counter has run out.
+    pub fn tick_timers(&mut self) -> bool
     {
-        self.index = self.index.wrapping_add(self.general_registers[vx as usize] as u16);
+        self.timer_ticks  += 1;
+        self.timer_delay  = self.timer_delay.saturating_sub(1);
+        self.buzzer_delay = self.buzzer_delay.saturating_sub(1);
+        self.debounce_keys();
 
-        pc_Increment::Next
+        self.buzzer_delay == 0
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_F_VX(&mut self, vx: u8) -> pc_Increment
+    /// Subtracts the indicated number of ticks from the delay counter, stopping at 0. Fractions of a tick are carried over to the
+    /// next call, so subtracting 0.5 twice takes off one tick. Values that are not positive and finite (including NaN) are ignored.
+    #[deprecated(note = "the timers count whole 60Hz ticks; use tick_timers or set_timers")]
+    pub fn subtract_from_delaycounter(&mut self, value_to_subtract: f32)
     {
-        self.index = 5 * (self.general_registers[vx as usize] as u16);
+        if !(value_to_subtract.is_finite() && (value_to_subtract > 0.0))
+        {
+            return;
+        }
 
-        pc_Increment::Next
+        let ticks: u8 = Chip8::whole_ticks(&mut self.delay_fraction, value_to_subtract);
+        self.timer_delay = self.timer_delay.saturating_sub(ticks);
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_B_VX(&mut self, vx: u8) -> pc_Increment
+    /// Subtracts the indicated number of ticks from the buzzer counter, stopping at 0. Returns true if the buzzer counter has run
+    /// out. Fractions of a tick are carried over to the next call, and values that are not positive and finite (including NaN) are
+    /// ignored.
+    #[deprecated(note = "the timers count whole 60Hz ticks; use tick_timers or set_timers")]
+    pub fn subtract_from_buzzercounter(&mut self, value_to_subtract: f32) -> bool
     {
-        let value: u8    = self.general_registers[vx as usize];
-        let mut result: [u8; 3] = [0,0,0];
-        result[0] = value / 100;
-        result[1] = (value - result[0]) / 10;
-        result[2] = value - (result[0] + result[1]);
+        if value_to_subtract.is_finite() && (value_to_subtract > 0.0)
+        {
+            let ticks: u8 = Chip8::whole_ticks(&mut self.buzzer_fraction, value_to_subtract);
+            self.buzzer_delay = self.buzzer_delay.saturating_sub(ticks);
+        }
+
+        self.buzzer_delay == 0
+    }
+
+    // Adds value to the fraction of a tick carried over from earlier calls, and returns the whole ticks in the total, keeping the
+    // fraction left over for next time.
+    fn whole_ticks(fraction: &mut f32, value: f32) -> u8
+    {
+        let total: f32 = *fraction + value;
+        let whole: f32 = total.floor();
+        *fraction = total - whole;
+
+        whole.min(255.0) as u8
+    }
+
+    /// Returns the time, in seconds, that the program has been running for, counted in 60Hz ticks by tick_timers since the last reset.
+    pub fn emulated_time(&self) -> f64
+    {
+        (self.timer_ticks as f64) / 60.0
+    }
 
-        for i in 0..3
+    /// Returns true while the sound timer is above zero, which is when the buzzer should sound.
+    pub fn is_beeping(&self) -> bool
+    {
+        self.buzzer_delay > 0
+    }
+
+    /// Returns the number of 60Hz frames until the sound timer reaches zero and the buzzer stops, which is the sound timer's value.
+    pub fn sound_end_in_frames(&self) -> u8
+    {
+        self.buzzer_delay
+    }
+
+    /// Returns the values of the delay and sound timers.
+    pub fn timers(&self) -> TimerState
+    {
+        TimerState
+        {
+            delay: self.timer_delay,
+            sound: self.buzzer_delay
+        }
+    }
+
+    /// Sets the delay and sound timers.
+    pub fn set_timers(&mut self, timers: TimerState)
+    {
+        self.timer_delay  = timers.delay;
+        self.buzzer_delay = timers.sound;
+    }
+
+    /// Returns the value of the delay counter as a float. The counter holds whole ticks, so this is always timers().delay.
+    #[deprecated(note = "the timers count whole 60Hz ticks; use timers")]
+    pub fn delay_timer_raw(&self) -> f32
+    {
+        self.timer_delay as f32
+    }
+
+    /// Returns the value of the buzzer counter as a float. The counter holds whole ticks, so this is always timers().sound.
+    #[deprecated(note = "the timers count whole 60Hz ticks; use timers")]
+    pub fn sound_timer_raw(&self) -> f32
+    {
+        self.buzzer_delay as f32
+    }
+
+    /// Returns the font region of memory, FONT_SIZE bytes from the font's base (FONT_BASE unless changed with load_font), as used
+    /// by Fx29. The region is cut short at the end of memory. Five bytes per glyph, from 0 to F. This
+    /// reads memory directly, so a program that overwrites the font is reflected here.
+    pub fn font_data(&self) -> &[u8]
+    {
+        let start: usize = (self.font_base as usize).min(self.memory_size());
+        &self.memory[start..(start + FONT_SIZE).min(self.memory_size())]
+    }
+
+    // Loads the default font into FONT_BASE.
+    fn load_default_font(&mut self)
+    {
+        let font_set: [u8; FONT_SIZE] = 
+        [
+            0xF0, 0x90, 0x90, 0x90, 0xF0,		// 0
+	        0x20, 0x60, 0x20, 0x20, 0x70,		// 1
+	        0xF0, 0x10, 0xF0, 0x80, 0xF0,		// 2
+	        0xF0, 0x10, 0xF0, 0x10, 0xF0,		// 3
+    	    0x90, 0x90, 0xF0, 0x10, 0x10,		// 4
+    	    0xF0, 0x80, 0xF0, 0x10, 0xF0,		// 5
+    	    0xF0, 0x80, 0xF0, 0x90, 0xF0,		// 6
+    	    0xF0, 0x10, 0x20, 0x40, 0x40,		// 7
+    	    0xF0, 0x90, 0xF0, 0x90, 0xF0,		// 8
+    	    0xF0, 0x90, 0xF0, 0x10, 0xF0,		// 9
+	        0xF0, 0x90, 0xF0, 0x90, 0x90,		// A
+    	    0xE0, 0x90, 0xE0, 0x90, 0xE0,		// B
+    	    0xF0, 0x80, 0x80, 0x80, 0xF0,		// C
+    	    0xE0, 0x90, 0x90, 0x90, 0xE0,		// D
+    	    0xF0, 0x80, 0xF0, 0x80, 0xF0,		// E
+    	    0xF0, 0x80, 0xF0, 0x80, 0x80		// F
+        ];
+
+        //Load copy fontset into the devices memory
+        for i in 0..(FONT_SIZE as u16)
+        {
+            self.set_memory_byte(FONT_BASE + i, font_set[i as usize]);
+        }
+        self.font_base = FONT_BASE;
+    }
+
+    /// Ends a wait started by LD Vx, K without a keypress: value is written to Vx, and execution continues with the next instruction.
+    /// Does nothing if the device isn't waiting for a keypress.
+    pub fn cancel_key_wait(&mut self, value: u8)
+    {
+        if self.device_state != CpuState::WaitingForKeypress
+        {
+            return;
+        }
+
+        self.device_state = CpuState::Ready;
+        self.general_registers[self.temp_vx as usize] = value;
+        self.advance_program_counter(self.program_counter, 2);
+    }
+
+    /// Returns the register and key, as (register, key), of the most recent LD Vx, K instruction to be resumed by a keypress.
+    pub fn last_key_wait(&self) -> Option<(u8, u8)>
+    {
+        self.last_key_wait
+    }
+
+    /// Returns true if the last call to execute took the skip branch of SE, SNE, SKP, or SKNP, advancing the PC by 4.
+    pub fn last_instruction_skipped(&self) -> bool
+    {
+        self.last_instruction_skipped
+    }
+
+    /// Returns the instruction that last set VF as a side effect, or None if no such instruction has run since the last reset.
+    pub fn last_vf_source(&self) -> Option<VfSource>
+    {
+        self.last_vf_source
+    }
+
+    /// Returns the position, as (x, y), and the sprite data of the most recent DRW. When several XO-CHIP planes were drawn, the data
+    /// holds each plane's sprite in turn.
+    pub fn last_sprite(&self) -> Option<(u8, u8, Vec<u8>)>
+    {
+        self.last_sprite.clone()
+    }
+
+    /// Disassembles the instructions from before instructions ahead of the program counter to after instructions past it. Each line
+    /// is (address, opcode, disassembly, is the current instruction). The window is cut short at the start and end of memory.
+    pub fn disassemble_window(&self, before: u16, after: u16) -> Vec<(u16, u16, String, bool)>
+    {
+        let first: u16 = self.program_counter.saturating_sub(before.saturating_mul(2));
+        let last:  u16 = self.program_counter.saturating_add(after.saturating_mul(2)).min((self.memory_size() - 2) as u16);
+
+        (first..=last).step_by(2)
+            .map(|address| {
+                let opcode: u16 = ((self.memory[address as usize] as u16) << 8) | (self.memory[(address as usize) + 1] as u16);
+                (address, opcode, disassemble(opcode), address == self.program_counter)
+            })
+            .collect()
+    }
+
+    /// Returns the address of every instruction that can be reached from entry, found by following the program through memory
+    /// without running it. JP and CALL targets and skipped-over instructions are followed, while RET, EXIT, and unknown opcodes end
+    /// a path. JP V0's target depends on V0, so paths end there too, and code only reached through it is left out.
+    pub fn reachable_code(&self, entry: u16) -> BTreeSet<u16>
+    {
+        let mut reachable: BTreeSet<u16> = BTreeSet::new();
+        let mut pending:   Vec<u16> = vec![entry & self.memory_mask];
+
+        while let Some(address) = pending.pop()
         {
-            if (self.index + i) >= 4096
+            if !reachable.insert(address)
             {
-                break;
+                continue;
             }
 
-            self.memory[(self.index + i) as usize] = result[i as usize];
+            let high_byte: u8 = self.memory[address as usize];
+            let low_byte:  u8 = self.memory[((address as usize) + 1) % self.memory_size()];
+            let next:      u16 = address.wrapping_add(2) & self.memory_mask;
+            match decode(((high_byte as u16) << 8) | (low_byte as u16))
+            {
+                Instruction::Ret | Instruction::Exit | Instruction::JpV0(_) | Instruction::Unknown(_) => (),
+                Instruction::Jp(target)   => pending.push(target),
+                Instruction::Call(target) => { pending.push(target); pending.push(next); },
+                Instruction::SeVx {..} | Instruction::SneVx {..} | Instruction::SeVxVy {..} | Instruction::SneVxVy {..} |
+                Instruction::SkpVx {..} | Instruction::SknpVx {..} => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(2) & self.memory_mask);
+                },
+                _ => pending.push(next)
+            }
         }
 
-        pc_Increment::Next
+        reachable
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_iIi_VX(&mut self, vx: u8) -> pc_Increment
+    /// Returns the return addresses of the active subroutine calls, outermost call first.
+    pub fn call_stack(&self) -> Vec<u16>
     {
-        for register_number in 0..=vx
+        //Each slot holds the address of the CALL itself (see opcode_CALL), so 2 is added to get the return address.
+        self.stack[..(self.stack_pointer as usize)].iter()
+            .map(|call_address| call_address.wrapping_add(2) & self.memory_mask)
+            .collect()
+    }
+
+    /// Returns what the device is currently doing.
+    pub fn cpu_state(&self) -> CpuState
+    {
+        self.device_state
+    }
+
+    /// Returns the fault that stopped the device, if any.
+    pub fn fault(&self) -> Option<Fault>
+    {
+        match self.device_state
         {
-            if (self.index + (register_number as u16) >= 4096) || (vx > 0xF)
+            CpuState::Faulted(fault) => Some(fault),
+            _                        => None
+        }
+    }
+
+    /// Executes up to the indicated number of instructions. Returns the number actually executed, which is fewer than requested if
+    /// the device is left waiting for a keypress, faults, or halts.
+    pub fn run_budget(&mut self, cycles: u32) -> u32
+    {
+        let mut executed: u32 = 0;
+        while executed < cycles
+        {
+            match self.device_state
             {
-                break;
+                CpuState::WaitingForKeypress => {
+                    //Polling the keypad doesn't execute an instruction, so it isn't counted against the budget.
+                    self.execute();
+                    if self.device_state == CpuState::WaitingForKeypress
+                    {
+                        break;
+                    }
+                },
+                CpuState::Faulted(_) | CpuState::Halted => break,
+                CpuState::Ready      => ()
             }
 
-            self.memory[(self.index + (register_number as u16)) as usize] = self.general_registers[register_number as usize];
+            self.execute();
+            executed += 1;
         }
 
-        pc_Increment::Next
+        executed
     }
 
-    //TODO: bounds check for general_registers
-    #[allow(non_snake_case)]
-    fn opcode_LD_VX_iIi(&mut self, vx: u8) -> pc_Increment
+    /// Runs one 60Hz frame: executes up to cycles_per_frame instructions, scaled by the speed multiplier (see run_budget), then ticks
+    /// the timers once.
+    pub fn run_frame(&mut self, cycles_per_frame: u32)
     {
-        for register_number in 0..=vx
+        if self.frame_history_depth > 0
         {
-            if ((self.index + (register_number as u16)) >= 4096) || (vx > 0xF)
+            if self.frame_history.len() == self.frame_history_depth
             {
-                break;
+                self.frame_history.remove(0);
             }
+            self.frame_history.push(self.snapshot());
+        }
+
+        self.step_frame(cycles_per_frame);
+    }
+
+    // Runs one frame for run_frame and fast_forward, without snapshotting it for the frame history.
+    fn step_frame(&mut self, cycles_per_frame: u32)
+    {
+        self.collisions_this_frame = 0;
+        self.run_budget(((cycles_per_frame as f32) * self.speed_multiplier).round() as u32);
+        self.tick_timers();
+        self.apply_frame_pokes();
+    }
 
-            self.general_registers[register_number as usize] = self.memory[(self.index + (register_number as u16)) as usize];
+    /// Adds a poke that writes byte to address at the end of every frame run by run_frame, overriding anything the program wrote
+    /// there during the frame. Adding a poke for an address that already has one replaces it. Addresses outside of memory are ignored.
+    pub fn add_frame_poke(&mut self, address: u16, byte: u8)
+    {
+        if (address as usize) >= self.memory_size()
+        {
+            return;
         }
 
-        pc_Increment::Next
+        self.frame_pokes.retain(|(poke_address, _)| *poke_address != address);
+        self.frame_pokes.push((address, byte));
+    }
+
+    /// Removes all pokes added by add_frame_poke.
+    pub fn clear_frame_pokes(&mut self)
+    {
+        self.frame_pokes.clear();
     }
-}
\ No newline at end of file
+
+    /// Starts keeping a snapshot of the device at the start of each of the last depth frames run by run_frame, so that they can be
+    /// undone with rewind_frame. Any frames already kept are discarded, and a depth of 0 stops keeping them.
+    pub fn enable_frame_history(&mut self, depth: usize)
+    {
+        self.frame_history.clear();
+        self.frame_history_depth = depth;
+    }
+
+    /// Undoes the last frame run by run_frame, restoring the device to how it was at the end of the frame before it. Returns false,
+    /// leaving the device untouched, if no frames are kept.
+    pub fn rewind_frame(&mut self) -> bool
+    {
+        match self.frame_history.pop()
+        {
+            Some(snapshot) =>
+            {
+                self.restore(&snapshot);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn apply_frame_pokes(&mut self)
+    {
+        for (address, byte) in self.frame_pokes.iter()
+        {
+            self.memory[*address as usize] = *byte;
+        }
+    }
+
+    /// Runs one frame (see run_frame) and returns the screen as an RGBA buffer, row-major at the current resolution, with lit pixels
+    /// colored on and unlit pixels colored off.
+    pub fn run_frame_rgba(&mut self, cycles: u32, on: [u8; 4], off: [u8; 4]) -> Vec<u8>
+    {
+        self.run_frame(cycles);
+
+        let mut rgba: Vec<u8> = Vec::with_capacity(self.screen_size() * 4);
+        for row in 0..(self.screen_height() as usize)
+        {
+            for col in 0..(self.screen_width() as usize)
+            {
+                if (self.screen[row] & Chip8::column_bit(col)) != 0
+                {
+                    rgba.extend_from_slice(&on);
+                }
+                else
+                {
+                    rgba.extend_from_slice(&off);
+                }
+            }
+        }
+
+        rgba
+    }
+
+    /// Sets the number that the instructions executed per frame by run_frame (and so fast_forward) are multiplied by. 2.0 runs the
+    /// program twice as fast, while the timers still tick once per frame. Negative and non-finite values are ignored.
+    pub fn set_speed_multiplier(&mut self, mult: f32)
+    {
+        if mult.is_finite() && (mult >= 0.0)
+        {
+            self.speed_multiplier = mult;
+        }
+    }
+
+    /// Returns the number that the instructions executed per frame by run_frame are multiplied by.
+    pub fn speed_multiplier(&self) -> f32
+    {
+        self.speed_multiplier
+    }
+
+    /// Runs the indicated number of frames of DEFAULT_CYCLES_PER_FRAME instructions, as fast as possible, for skipping intros. The
+    /// device ends up exactly as if run_frame(DEFAULT_CYCLES_PER_FRAME) had been called once per frame, frame history included. With
+    /// frame history enabled this is faster, as only the frames that are still kept at the end are snapshotted; otherwise it runs
+    /// at the same speed as the run_frame loop.
+    pub fn fast_forward(&mut self, frames: u32)
+    {
+        //The snapshots of all but the last frame_history_depth frames would be dropped from the history before the end.
+        let unkept_frames: usize = (frames as usize).saturating_sub(self.frame_history_depth);
+        for frame in 0..(frames as usize)
+        {
+            if frame < unkept_frames
+            {
+                self.step_frame(DEFAULT_CYCLES_PER_FRAME);
+            }
+            else
+            {
+                self.run_frame(DEFAULT_CYCLES_PER_FRAME);
+            }
+        }
+    }
+
+    /// Runs a fixed drawing and arithmetic loop for the indicated duration and returns the number of instructions executed per
+    /// second. The loop runs on a separate device with this device's quirks and modes, so this device is left untouched.
+    pub fn benchmark_ips(&self, duration: std::time::Duration) -> f64
+    {
+        let benchmark_program: [u16; 8] =
+        [
+            0x6000, // LD V0, 0
+            0x6100, // LD V1, 0
+            0xF029, // LD F, V0
+            0xD015, // DRW V0, V1, 5
+            0x7001, // ADD V0, 1
+            0x8114, // ADD V1, V1
+            0x8103, // XOR V1, V0
+            0x1206  // JP 0x206
+        ];
+
+        let mut device: Chip8 = Chip8::builder().quirks(self.quirks).super_chip(self.super_chip).xo_chip(self.xo_chip).build();
+        for (i, word) in benchmark_program.iter().enumerate()
+        {
+            device.set_memory_word(PROGRAM_BASE + ((i as u16) * 2), *word);
+        }
+
+        let start: std::time::Instant = std::time::Instant::now();
+        let mut executed: u64 = 0;
+        while start.elapsed() < duration
+        {
+            executed += device.run_budget(1000) as u64;
+        }
+        let elapsed: f64 = start.elapsed().as_secs_f64();
+
+        (executed as f64) / elapsed
+    }
+
+    /// Executes instructions until a DRW has been executed or max_cycles instructions have run, whichever comes first. Returns true
+    /// if a DRW was executed. Like the display wait of the original interpreter, this gives exactly one sprite draw per frame.
+    pub fn run_until_draw_or_budget(&mut self, max_cycles: u32) -> bool
+    {
+        for _ in 0..max_cycles
+        {
+            if self.device_state == CpuState::WaitingForKeypress
+            {
+                self.execute();
+            }
+
+            if self.device_state != CpuState::Ready
+            {
+                return false;
+            }
+
+            let is_draw: bool = (self.memory[self.program_counter as usize] & 0xF0) == 0xD0;
+            self.execute();
+            if is_draw
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Fully executes one instruction. Automatically increments the program counter as needed. Returns the instruction that was
+    /// executed, or None if no instruction was, because the device is waiting for a keypress, faulted, or halted.
+    pub fn execute(&mut self) -> Option<Instruction>
+    {
+        let mut pc_op: pc_Increment = pc_Increment::Jump;
+        let mut executed: Option<Instruction> = None;
+        match self.device_state
+        {
+            CpuState::Faulted(_) | CpuState::Halted => (),
+            CpuState::WaitingForKeypress => { pc_op = self.check_for_new_key_pressed(); },
+            CpuState::Ready                => {
+                if (self.program_counter & 1) != 0
+                {
+                    self.warn(Warning::MisalignedFetch(self.program_counter));
+                }
+
+                //The low byte wraps around so an instruction fetched from the last byte of memory can't read out of bounds.
+                let high_byte: u8 = self.memory[ self.program_counter as usize];
+                let low_byte:  u8 = self.memory[((self.program_counter as usize) + 1) % self.memory_size()];
+                self.opcode = ((high_byte as u16) << 8) | (low_byte as u16);
+                self.record_opcode_kind();
+
+                //The state before the first instruction is kept so that seek_to_instruction can replay from it.
+                if self.seek_origin.is_none()
+                {
+                    self.seek_origin = Some(Box::new(self.snapshot()));
+                }
+
+                //Counted before dispatch so that CLS and DRW can reset it to zero.
+                self.instructions_since_draw = self.instructions_since_draw.saturating_add(1);
+                self.instructions_executed += 1;
+
+                //Decode the current instruction then execute the instruction. Super-CHIP and XO-CHIP instructions are only executed in
+                //their modes; otherwise the Super-CHIP ones are treated as SYS and the XO-CHIP ones are skipped.
+                let super_chip: bool = self.super_chip || self.xo_chip;
+                let xo_chip:    bool = self.xo_chip;
+                let instruction: Instruction = decode(self.opcode);
+                executed = Some(instruction);
+                pc_op = match instruction
+                {
+                    Instruction::Cls                      => self.opcode_CLS       (), //t
+                    Instruction::Ret                      => self.opcode_RET       (), //t
+                    Instruction::ScrollDown {n} if super_chip => self.opcode_SCD   (n),
+                    Instruction::ScrollRight    if super_chip => self.opcode_SCR   (),
+                    Instruction::ScrollLeft     if super_chip => self.opcode_SCL   (),
+                    Instruction::Exit           if super_chip => self.opcode_EXIT  (),
+                    Instruction::Low            if super_chip => self.opcode_LOW   (),
+                    Instruction::High           if super_chip => self.opcode_HIGH  (),
+                    Instruction::ScrollDown {..} | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Exit |
+                    Instruction::Low | Instruction::High |
+                    Instruction::Sys(_)                   => self.opcode_SYS       (), //t
+                    Instruction::Jp(nnn)                  => self.opcode_JP        (nnn), //t
+                    Instruction::Call(nnn)                => self.opcode_CALL      (nnn), //t
+                    Instruction::SeVx     {x, kk}         => self.opcode_SE_VX     (x, kk), //t
+                    Instruction::SneVx    {x, kk}         => self.opcode_SNE_VX    (x, kk), //t
+                    Instruction::SeVxVy   {x, y}          => self.opcode_SE_VX_VY  (x, y), //t
+                    Instruction::SaveVxVy {x, y} if xo_chip => self.opcode_SAVE_VX_VY(x, y),
+                    Instruction::LoadVxVy {x, y} if xo_chip => self.opcode_LOAD_VX_VY(x, y),
+                    Instruction::LdVx     {x, kk}         => self.opcode_LD_VX     (x, kk), //t
+                    Instruction::AddVx    {x, kk}         => self.opcode_ADD_VX    (x, kk), //t
+                    Instruction::LdVxVy   {x, y}          => self.opcode_LD_VX_VY  (x, y), //t
+                    Instruction::OrVxVy   {x, y}          => self.opcode_OR_VX_VY  (x, y), //t
+                    Instruction::AndVxVy  {x, y}          => self.opcode_AND_VX_VY (x, y), //t
+                    Instruction::XorVxVy  {x, y}          => self.opcode_XOR_VX_VY (x, y), //t
+                    Instruction::AddVxVy  {x, y}          => self.opcode_ADD_VX_VY (x, y), //t
+                    Instruction::SubVxVy  {x, y}          => self.opcode_SUB_VX_VY (x, y), //t
+                    Instruction::ShrVx    {x, y}          => self.opcode_SHR_VX    (x, y), //t
+                    Instruction::SubnVxVy {x, y}          => self.opcode_SUBN_VX_VY(x, y), //t
+                    Instruction::ShlVx    {x, y}          => self.opcode_SHL_VX    (x, y), //t
+                    Instruction::SneVxVy  {x, y}          => self.opcode_SNE_VX_VY (x, y), //t
+                    Instruction::LdI(nnn)                 => self.opcode_LD_I      (nnn), //t
+                    Instruction::JpV0(nnn)                => self.opcode_JP_V0     (nnn), //t
+                    Instruction::RndVx    {x, kk}         => self.opcode_RND_VX    (x, kk), //Not working as intended
+                    Instruction::DrwVxVy  {x, y, n}       => self.opcode_DRW_VX_VY (x, y, n), //t
+                    Instruction::SkpVx    {x}             => self.opcode_SKP_VX    (x), //t
+                    Instruction::SknpVx   {x}             => self.opcode_SKNP_VX   (x),
+                    Instruction::Plane    {n} if xo_chip  => self.opcode_PLANE     (n),
+                    Instruction::Audio        if xo_chip  => self.opcode_AUDIO     (),
+                    Instruction::LdVxDt   {x}             => self.opcode_LD_VX_DT  (x),
+                    Instruction::LdVxK    {x}             => self.opcode_LD_VX_K   (x), //t
+                    Instruction::LdDtVx   {x}             => self.opcode_LD_DT_VX  (x),
+                    Instruction::LdStVx   {x}             => self.opcode_LD_ST_VX  (x),
+                    Instruction::AddIVx   {x}             => self.opcode_ADD_I_VX  (x), //t
+                    Instruction::LdFVx    {x}             => self.opcode_LD_F_VX   (x), //t
+                    Instruction::LdBVx    {x}             => self.opcode_LD_B_VX   (x),
+                    Instruction::Pitch    {x} if xo_chip  => self.opcode_PITCH     (x),
+                    Instruction::LdIVx    {x}             => self.opcode_LD_iIi_VX (x),
+                    Instruction::LdVxI    {x}             => self.opcode_LD_VX_iIi (x),
+                    Instruction::SaveVxVy {..} | Instruction::LoadVxVy {..} | Instruction::Plane {..} | Instruction::Audio |
+                    Instruction::Pitch {..} | Instruction::Unknown(_) => pc_Increment::Next
+                }
+            }
+        }
+
+        self.record_register_watches();
+
+        //Increment the program counter as previously indicated by the instruction
+        self.last_instruction_skipped = matches!(pc_op, pc_Increment::Skip);
+        match pc_op
+        {
+            pc_Increment::Next => self.advance_program_counter(self.program_counter, 2),
+            pc_Increment::Skip => self.advance_program_counter(self.program_counter, 4),
+            _ => ()
+        }
+
+        executed
+    }
+
+    // Sets the program counter to from + amount, wrapping around to the start of memory (with a warning) if it runs past the end.
+    fn advance_program_counter(&mut self, from: u16, amount: u16)
+    {
+        let next_pc: usize = (from as usize) + (amount as usize);
+        if next_pc >= self.memory_size()
+        {
+            self.warn(Warning::ProgramCounterWrapped(from));
+        }
+
+        self.program_counter = (next_pc % self.memory_size()) as u16;
+    }
+
+    //Function for execution of CLS opcode. Clears the screen.
+    #[allow(non_snake_case)]
+    fn opcode_CLS(&mut self) -> pc_Increment
+    {
+        let selected_planes: u8 = if self.xo_chip { self.selected_planes } else { 0b01 };
+        if (selected_planes & 0b01) != 0 {self.screen       = [0; MAX_SCREEN_HEIGHT]}
+        if (selected_planes & 0b10) != 0 {self.second_plane = [0; MAX_SCREEN_HEIGHT]}
+        self.instructions_since_draw = 0;
+
+        pc_Increment::Next
+    }
+
+    //Function for execution of the Super-CHIP SCD opcode. Scrolls the screen down n rows.
+    #[allow(non_snake_case)]
+    fn opcode_SCD(&mut self, n: u8) -> pc_Increment
+    {
+        let height: usize = self.screen_height() as usize;
+        let n:      usize = (n as usize).min(height);
+        for plane_rows in self.selected_plane_rows()
+        {
+            plane_rows.copy_within(..(height - n), n);
+            plane_rows[..n].fill(0);
+        }
+
+        pc_Increment::Next
+    }
+
+    //Function for execution of the Super-CHIP SCR opcode. Scrolls the screen right 4 pixels.
+    #[allow(non_snake_case)]
+    fn opcode_SCR(&mut self) -> pc_Increment
+    {
+        let width_mask: u128 = !0 << (128 - (self.screen_width() as u32));
+        for plane_rows in self.selected_plane_rows()
+        {
+            plane_rows.iter_mut().for_each(|row| *row = (*row >> 4) & width_mask);
+        }
+
+        pc_Increment::Next
+    }
+
+    //Function for execution of the Super-CHIP SCL opcode. Scrolls the screen left 4 pixels.
+    #[allow(non_snake_case)]
+    fn opcode_SCL(&mut self) -> pc_Increment
+    {
+        for plane_rows in self.selected_plane_rows()
+        {
+            plane_rows.iter_mut().for_each(|row| *row <<= 4);
+        }
+
+        pc_Increment::Next
+    }
+
+    // Returns the rows of the planes drawn to by DRW and scrolled by SCD, SCR, and SCL. Outside of XO-CHIP mode this is only the
+    // first plane.
+    fn selected_plane_rows(&mut self) -> Vec<&mut [u128; MAX_SCREEN_HEIGHT]>
+    {
+        let selected_planes: u8 = if self.xo_chip { self.selected_planes } else { 0b01 };
+        let mut planes: Vec<&mut [u128; MAX_SCREEN_HEIGHT]> = Vec::new();
+        if (selected_planes & 0b01) != 0
+        {
+            planes.push(&mut self.screen);
+        }
+        if (selected_planes & 0b10) != 0
+        {
+            planes.push(&mut self.second_plane);
+        }
+
+        planes
+    }
+
+    //Function for execution of the Super-CHIP EXIT opcode. Stops the device until it is reset.
+    #[allow(non_snake_case)]
+    fn opcode_EXIT(&mut self) -> pc_Increment
+    {
+        self.device_state = CpuState::Halted;
+
+        pc_Increment::Jump
+    }
+
+    //Function for execution of the Super-CHIP LOW opcode. Switches to the 64x32 display.
+    #[allow(non_snake_case)]
+    fn opcode_LOW(&mut self) -> pc_Increment
+    {
+        self.switch_resolution(Resolution::Low);
+
+        pc_Increment::Next
+    }
+
+    //Function for execution of the Super-CHIP HIGH opcode. Switches to the 128x64 display.
+    #[allow(non_snake_case)]
+    fn opcode_HIGH(&mut self) -> pc_Increment
+    {
+        self.switch_resolution(Resolution::High);
+
+        pc_Increment::Next
+    }
+
+    // Switches to the indicated resolution. The screen is cleared, or with the clear_on_mode_switch quirk disabled, scaled to fit:
+    // each low resolution pixel becomes a 2x2 block, and each 2x2 block becomes the low resolution pixel at its top left.
+    fn switch_resolution(&mut self, resolution: Resolution)
+    {
+        if self.quirks.clear_on_mode_switch
+        {
+            self.reset_display_mode();
+            self.resolution = resolution;
+            return;
+        }
+
+        if resolution == self.resolution
+        {
+            return;
+        }
+
+        self.resolution = resolution;
+        let width:     usize = self.screen_width() as usize;
+        let height:    usize = self.screen_height() as usize;
+
+        for plane in 0..2
+        {
+            let plane_rows: &mut [u128; MAX_SCREEN_HEIGHT] = if plane == 0 { &mut self.screen } else { &mut self.second_plane };
+            let old_rows: [u128; MAX_SCREEN_HEIGHT] = *plane_rows;
+            *plane_rows = [0; MAX_SCREEN_HEIGHT];
+
+            for (row, row_bits) in plane_rows[..height].iter_mut().enumerate()
+            {
+                for col in 0..width
+                {
+                    let (old_row, old_col): (usize, usize) = match resolution
+                    {
+                        Resolution::High => (row / 2, col / 2),
+                        Resolution::Low  => (row * 2, col * 2)
+                    };
+                    if (old_rows[old_row] & Chip8::column_bit(old_col)) != 0
+                    {
+                        *row_bits |= Chip8::column_bit(col);
+                    }
+                }
+            }
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_SYS(&mut self) -> pc_Increment
+    {
+        pc_Increment::Next
+    }
+
+    //Returns to the instruction after the matching CALL. CALL pushes its own address rather than the next one, so 2 is added here.
+    #[allow(non_snake_case)]
+    fn opcode_RET(&mut self) -> pc_Increment
+    {
+        if self.stack_pointer == 0
+        {
+            if !self.quirks.lenient_stack
+            {
+                self.device_state = CpuState::Faulted(Fault::StackUnderflow);
+                return pc_Increment::Jump;
+            }
+            self.stack_pointer = self.stack.len() as u8;
+        }
+
+        self.stack_pointer -= 1;
+        self.advance_program_counter(self.stack[self.stack_pointer as usize], 2);
+
+        pc_Increment::Jump
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_JP(&mut self, nnn: u16) -> pc_Increment
+    {
+        self.program_counter = nnn;
+
+        pc_Increment::Jump
+    }
+
+    //Pushes the address of the CALL itself (not the next instruction) then jumps. RET relies on this, see opcode_RET.
+    //The stack pointer is the number of slots in use, so the next push goes to stack[stack_pointer].
+    #[allow(non_snake_case)]
+    fn opcode_CALL(&mut self, nnn: u16) -> pc_Increment
+    {
+        if (self.stack_pointer as usize) == self.stack.len()
+        {
+            if !self.quirks.lenient_stack
+            {
+                self.device_state = CpuState::Faulted(Fault::StackOverflow);
+                return pc_Increment::Jump;
+            }
+            self.stack_pointer = 0;
+        }
+
+        self.stack[self.stack_pointer as usize] = self.program_counter;
+        self.stack_pointer += 1;
+        self.program_counter = nnn;
+
+        pc_Increment::Jump
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SE_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    {
+        if kk == self.general_registers[vx as usize]
+        {
+            pc_Increment::Skip // Skip the next instruction
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SNE_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    {
+        if kk != self.general_registers[vx as usize]
+        {
+            pc_Increment::Skip // Skip the next instruction
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SE_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        if self.general_registers[vx as usize] == self.general_registers[vy as usize]
+        {
+            pc_Increment::Skip // Skip the next instruction
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    //Function for execution of the XO-CHIP SAVE opcode. Stores registers Vx through Vy (in either direction) starting at I. I is
+    //left unchanged.
+    #[allow(non_snake_case)]
+    fn opcode_SAVE_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        let registers: Vec<u8> = if vx <= vy { (vx..=vy).collect() } else { (vy..=vx).rev().collect() };
+        for (offset, register_number) in registers.into_iter().enumerate()
+        {
+            let address: usize = ((self.index as usize) + offset) % self.memory_size();
+            self.memory[address] = self.general_registers[register_number as usize];
+        }
+
+        pc_Increment::Next
+    }
+
+    //Function for execution of the XO-CHIP LOAD opcode. Loads registers Vx through Vy (in either direction) starting from I. I is
+    //left unchanged.
+    #[allow(non_snake_case)]
+    fn opcode_LOAD_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        let registers: Vec<u8> = if vx <= vy { (vx..=vy).collect() } else { (vy..=vx).rev().collect() };
+        for (offset, register_number) in registers.into_iter().enumerate()
+        {
+            self.general_registers[register_number as usize] = self.memory[((self.index as usize) + offset) % self.memory_size()];
+        }
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] = kk;
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_ADD_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_add(kk);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] = self.general_registers[vy as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_OR_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] |= self.general_registers[vy as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_AND_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] &= self.general_registers[vy as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_XOR_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] ^= self.general_registers[vy as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_ADD_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        if (self.general_registers[vx as usize] as u16) + (self.general_registers[vy as usize] as u16) > 0xFF
+        {
+            self.general_registers[0xF] = 1;
+        }
+        else
+        {
+            self.general_registers[0xF] = 0;
+        }
+        self.general_registers[vx as usize] = self.general_registers[vx as usize].wrapping_add(self.general_registers[vy as usize]);
+        self.last_vf_source = Some(VfSource::Add);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SUB_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        //VF is set to NOT borrow.
+        let (result, borrow) = self.general_registers[vx as usize].overflowing_sub(self.general_registers[vy as usize]);
+        self.general_registers[0xF] = if borrow { 0 } else { 1 };
+        self.general_registers[vx as usize] = result;
+        self.last_vf_source = Some(VfSource::Sub);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SHR_VX(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        if self.quirks.shift_uses_vy
+        {
+            self.general_registers[vx as usize] = self.general_registers[vy as usize];
+        }
+
+        if (self.general_registers[vx as usize] & 1) != 0
+        {
+            self.general_registers[0xF] = 1;
+        }
+        else
+        {
+            self.general_registers[0xF] = 0;
+        }
+        self.general_registers[vx as usize] >>= 1;
+        self.last_vf_source = Some(VfSource::Shr);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SUBN_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        //VF is set to NOT borrow.
+        let (result, borrow) = self.general_registers[vy as usize].overflowing_sub(self.general_registers[vx as usize]);
+        self.general_registers[0xF] = if borrow { 0 } else { 1 };
+        self.general_registers[vx as usize] = result;
+        self.last_vf_source = Some(VfSource::Subn);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SHL_VX(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        if self.quirks.shift_uses_vy
+        {
+            self.general_registers[vx as usize] = self.general_registers[vy as usize];
+        }
+
+        if (self.general_registers[vx as usize] & 0b10000000) == 0
+        {
+            self.general_registers[0xF] = 0;
+        }
+        else
+        {
+            self.general_registers[0xF] = 1;
+        }
+
+        self.general_registers[vx as usize] <<= 1;
+        self.last_vf_source = Some(VfSource::Shl);
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_SNE_VX_VY(&mut self, vx: u8, vy: u8) -> pc_Increment
+    {
+        if self.general_registers[vx as usize] != self.general_registers[vy as usize]
+        {
+            pc_Increment::Skip // Skips next instruction
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_LD_I(&mut self, nnn: u16) -> pc_Increment
+    {
+        self.index = nnn;
+        self.index_from_font = false;
+
+        pc_Increment::Next
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_JP_V0(&mut self, nnn: u16) -> pc_Increment
+    {
+        let target: usize = (nnn as usize) + (self.general_registers[0] as usize);
+        self.program_counter = (target % self.memory_size()) as u16;
+
+        pc_Increment::Jump
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_RND_VX(&mut self, vx: u8, kk: u8) -> pc_Increment
+    {
+        let random_byte: u8 = match self.custom_rng.as_mut()
+        {
+            Some(rng) => rng.next_u8(),
+            None      => self.rng.next_u32() as u8
+        };
+        self.general_registers[vx as usize] = kk & random_byte;
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers, index, memory, sprite wrapping
+    #[allow(non_snake_case)]
+    fn opcode_DRW_VX_VY(&mut self, vx: u8, vy: u8, n: u8) -> pc_Increment
+    {
+        let width:  u16 = self.screen_width()  as u16;
+        let height: u16 = self.screen_height() as u16;
+        let vx = vx % 64;
+        let vy = vy % 32;
+        let x_pos = self.general_registers[vx as usize];
+        let y_pos = self.general_registers[vy as usize];
+        self.general_registers[0xF] = 0;
+
+        //In Super-CHIP and XO-CHIP modes, Dxy0 draws a 16x16 sprite stored as two bytes per row.
+        let (sprite_rows, row_bytes): (u16, u16) = if (n == 0) && (self.super_chip || self.xo_chip) { (16, 2) } else { (n as u16, 1) };
+        let sprite_len: u16 = sprite_rows * row_bytes;
+
+        if self.strict_memory && !self.index_from_font && (sprite_len > 0) && (self.index >= self.font_base)
+            && ((self.index as usize) < (self.font_base as usize + FONT_SIZE))
+        {
+            self.warn(Warning::SpriteFromFontRegion);
+        }
+
+        //Outside of XO-CHIP mode only the first plane exists. When several planes are selected, each plane's sprite data follows the
+        //previous plane's in memory.
+        let selected_planes: u8 = if self.xo_chip { self.selected_planes } else { 0b01 };
+        let mut sprite_address: u16 = self.index;
+        //Reuses the last sprite's buffer, so that recording the sprite doesn't allocate on every draw.
+        let mut sprite_bytes: Vec<u8> = self.last_sprite.take().map_or_else(Vec::new, |(_, _, bytes)| bytes);
+        sprite_bytes.clear();
+
+        for plane in 0..2
+        {
+            if (selected_planes & (1 << plane)) == 0
+            {
+                continue;
+            }
+
+            for sprite_row in 0..sprite_rows
+            {
+                let mut sprite_row_bits: u16 = 0;
+                for byte in 0..row_bytes
+                {
+                    let address: usize = (sprite_address as usize) + ((sprite_row * row_bytes + byte) as usize);
+                    let sprite_byte: u8 = self.memory[address & (self.memory_mask as usize)];
+                    sprite_bytes.push(sprite_byte);
+                    sprite_row_bits = (sprite_row_bits << 8) | (sprite_byte as u16);
+                }
+
+                //In clip mode only the sprite's starting position wraps; rows and columns past the edges aren't drawn and can't
+                //collide. Otherwise the sprite wraps around to the other side of the screen.
+                let mut screen_row: u16 = ((y_pos as u16) % height) + sprite_row;
+                if screen_row >= height
+                {
+                    if self.quirks.clip_sprites
+                    {
+                        continue;
+                    }
+                    //Sprites are at most 16 rows tall and the screen at least 32, so wrapping once is enough.
+                    screen_row -= height;
+                }
+
+                //Line the sprite row up with the leftmost column, shift it across to x_pos, and bring any columns that went past the
+                //right edge back around to the left (or drop them in clip mode).
+                let sprite_bits: u128 = (sprite_row_bits as u128) << (128 - 8 * (row_bytes as u32));
+                let x_shift:     u32  = ((x_pos as u16) % width) as u32;
+                let mut row_bits: u128 = sprite_bits >> x_shift;
+                if !self.quirks.clip_sprites
+                {
+                    row_bits |= sprite_bits.checked_shl((width as u32) - x_shift).unwrap_or(0);
+                }
+                row_bits &= !0 << (128 - width);
+
+                let plane_rows: &mut [u128; MAX_SCREEN_HEIGHT] = if plane == 0 { &mut self.screen } else { &mut self.second_plane };
+                if (plane_rows[screen_row as usize] & row_bits) != 0
+                {
+                    self.general_registers[0xF] = 1;
+                }
+                plane_rows[screen_row as usize] ^= row_bits;
+            }
+
+            sprite_address = sprite_address.wrapping_add(sprite_len);
+        }
+
+        self.instructions_since_draw = 0;
+        if self.general_registers[0xF] == 1
+        {
+            self.collisions_this_frame = self.collisions_this_frame.saturating_add(1);
+        }
+
+        if self.quirks.draw_advances_i
+        {
+            self.index = self.index.wrapping_add(sprite_len);
+            self.index_from_font = false;
+        }
+
+        self.last_vf_source = Some(VfSource::Draw);
+        self.last_sprite = Some((x_pos, y_pos, sprite_bytes));
+
+        pc_Increment::Next
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_SKP_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        if vx > 0xF
+        {
+            return pc_Increment::Next;
+        }
+
+        //Keys past 0xF don't exist, so they are never pressed.
+        if self.keypad.get(self.general_registers[vx as usize] as usize) == Some(&KeyState::Pressed)
+        {
+            pc_Increment::Skip
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn opcode_SKNP_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        if vx > 0xF
+        {
+            return pc_Increment::Next;
+        }
+
+        //Keys past 0xF don't exist, so they are never pressed.
+        if self.keypad.get(self.general_registers[vx as usize] as usize) != Some(&KeyState::Pressed)
+        {
+            pc_Increment::Skip
+        }
+        else
+        {
+            pc_Increment::Next
+        }
+    }
+
+    //XO-CHIP only. Selects the planes used by CLS and DRW.
+    #[allow(non_snake_case)]
+    fn opcode_PLANE(&mut self, n: u8) -> pc_Increment
+    {
+        self.selected_planes = n & 0b11;
+
+        pc_Increment::Next
+    }
+
+    //XO-CHIP only. Loads the 16-byte audio pattern from memory starting at I.
+    #[allow(non_snake_case)]
+    fn opcode_AUDIO(&mut self) -> pc_Increment
+    {
+        for i in 0..16
+        {
+            self.audio_pattern[i] = self.memory[((self.index as usize) + i) % self.memory_size()];
+        }
+
+        pc_Increment::Next
+    }
+
+    //XO-CHIP only. Sets the audio pattern's playback pitch.
+    #[allow(non_snake_case)]
+    fn opcode_PITCH(&mut self, vx: u8) -> pc_Increment
+    {
+        self.audio_pitch = self.general_registers[vx as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for keypad
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX_DT(&mut self, vx: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] = self.timer_delay;
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX_K(&mut self, vx: u8) -> pc_Increment
+    {
+        self.temp_vx = vx;
+        self.device_state = CpuState::WaitingForKeypress;
+        self.save_keypad();
+
+        pc_Increment::Jump
+    }
+
+    //Executed after a keypress is performed
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX_K_CONT(&mut self, vx: u8, pressed_key: u8) -> pc_Increment
+    {
+        self.general_registers[vx as usize] = pressed_key;
+        self.last_key_wait = Some((vx, pressed_key));
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for keypad
+    #[allow(non_snake_case)]
+    fn opcode_LD_DT_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        self.timer_delay = self.general_registers[vx as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for keypad
+    #[allow(non_snake_case)]
+    fn opcode_LD_ST_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        self.buzzer_delay = self.general_registers[vx as usize];
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_ADD_I_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        self.index = self.index.wrapping_add(self.general_registers[vx as usize] as u16);
+        self.index_from_font = false;
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_F_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        self.index = self.font_base.wrapping_add(5 * (self.general_registers[vx as usize] as u16));
+        self.index_from_font = true;
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_B_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        let value: u8    = self.general_registers[vx as usize];
+        let mut result: [u8; 3] = [0,0,0];
+        result[0] =  value / 100;
+        result[1] = (value / 10) % 10;
+        result[2] =  value % 10;
+
+        for (i, digit) in result.iter().enumerate()
+        {
+            let address: usize = (self.index as usize) + i;
+            if address >= self.memory_size()
+            {
+                break;
+            }
+
+            self.memory[address] = *digit;
+        }
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_iIi_VX(&mut self, vx: u8) -> pc_Increment
+    {
+        for register_number in 0..=vx
+        {
+            let address: usize = (self.index as usize) + (register_number as usize);
+            if (address >= self.memory_size()) || (vx > 0xF)
+            {
+                break;
+            }
+
+            self.memory[address] = self.general_registers[register_number as usize];
+        }
+
+        pc_Increment::Next
+    }
+
+    //TODO: bounds check for general_registers
+    #[allow(non_snake_case)]
+    fn opcode_LD_VX_iIi(&mut self, vx: u8) -> pc_Increment
+    {
+        for register_number in 0..=vx
+        {
+            let address: usize = (self.index as usize) + (register_number as usize);
+            if (address >= self.memory_size()) || (vx > 0xF)
+            {
+                break;
+            }
+
+            self.general_registers[register_number as usize] = self.memory[address];
+        }
+
+        pc_Increment::Next
+    }
+}
+/// Packs a grid of pixels into sprite bytes, one byte per 8-pixel row. The leftmost pixel of each row is the most significant bit.
+pub fn grid_to_sprite(rows: &[[bool; 8]]) -> Vec<u8>
+{
+    let mut sprite: Vec<u8> = Vec::with_capacity(rows.len());
+    for row in rows
+    {
+        let mut byte: u8 = 0;
+        for (bit, pixel) in row.iter().enumerate()
+        {
+            if *pixel
+            {
+                byte |= 0b10000000 >> bit;
+            }
+        }
+
+        sprite.push(byte);
+    }
+
+    sprite
+}
+
+/// Returns the coordinates, as (row, column), of every pixel that differs between the two devices' screens, in row-major order.
+/// Returns None if the screens are at different resolutions, as their pixels can't be compared.
+pub fn screen_diff(a: &Chip8, b: &Chip8) -> Option<Vec<(u8, u8)>>
+{
+    if a.resolution() != b.resolution()
+    {
+        return None;
+    }
+
+    let height: usize = a.screen_height() as usize;
+    let width:  usize = a.screen_width()  as usize;
+
+    let mut differences: Vec<(u8, u8)> = Vec::new();
+    for row in 0..height
+    {
+        let changed: u128 = a.screen[row] ^ b.screen[row];
+        for col in 0..width
+        {
+            if (changed & Chip8::column_bit(col)) != 0
+            {
+                differences.push((row as u8, col as u8));
+            }
+        }
+    }
+
+    Some(differences)
+}
+
+/// Returns the number of instructions to run per frame, as passed to run_frame, for a clock_hz instruction clock at fps frames per
+/// second. The result is rounded to the nearest whole instruction, so cycles_for_framerate(700, 60) is 12. Returns 0 if fps is 0.
+pub fn cycles_for_framerate(clock_hz: u32, fps: u32) -> u32
+{
+    if fps == 0
+    {
+        return 0;
+    }
+
+    (((clock_hz as u64) + (fps as u64) / 2) / (fps as u64)) as u32
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Returns a hard reset device with the program loaded at PROGRAM_BASE.
+    fn device_with(program: &[u8]) -> Chip8
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.load_rom(program).unwrap();
+        device
+    }
+
+    // Executes count instructions.
+    fn step(device: &mut Chip8, count: usize)
+    {
+        for _ in 0..count
+        {
+            device.execute();
+        }
+    }
+
+    // Installs a warning hook on the device, returning the list that the warnings it raises are collected in.
+    fn collect_warnings(device: &mut Chip8) -> Rc<RefCell<Vec<Warning>>>
+    {
+        let warnings:      Rc<RefCell<Vec<Warning>>> = Rc::new(RefCell::new(Vec::new()));
+        let hook_warnings: Rc<RefCell<Vec<Warning>>> = warnings.clone();
+        device.set_warning_hook(Box::new(move |warning| hook_warnings.borrow_mut().push(warning)));
+        warnings
+    }
+
+    #[test]
+    fn se_and_sne_skip_the_next_instruction()
+    {
+        //LD V1, 05; SE V1, 05 skips to 0x206; SNE V1, 06 skips to 0x20A; SE V1, V1 skips to 0x20E.
+        let mut device: Chip8 = device_with(&[0x61, 0x05, 0x31, 0x05, 0x00, 0x00, 0x41, 0x06, 0x00, 0x00, 0x51, 0x10]);
+        step(&mut device, 2);
+        assert_eq!(device.program_counter(), 0x206);
+        step(&mut device, 1);
+        assert_eq!(device.program_counter(), 0x20A);
+        step(&mut device, 1);
+        assert_eq!(device.program_counter(), 0x20E);
+    }
+
+    #[test]
+    fn se_and_sne_fall_through_when_not_taken()
+    {
+        //LD V1, 05; LD V2, 06; SE V1, 06; SNE V1, 05; SE V1, V2.
+        let mut device: Chip8 = device_with(&[0x61, 0x05, 0x62, 0x06, 0x31, 0x06, 0x41, 0x05, 0x51, 0x20]);
+        step(&mut device, 5);
+        assert_eq!(device.program_counter(), 0x20A);
+    }
+
+    #[test]
+    fn equal_seeds_give_equal_random_numbers()
+    {
+        //RND V0 FF through RND V7 FF
+        let program: Vec<u8> = (0..8).flat_map(|x| vec![0xC0 | x, 0xFF]).collect();
+        let registers = |seed: u64| -> Vec<Option<u8>> {
+            let mut device: Chip8 = device_with(&program);
+            device.seed_rng(seed);
+            step(&mut device, 8);
+            (0..8).map(|x| device.register(x)).collect()
+        };
+
+        assert_eq!(registers(5), registers(5));
+        assert_ne!(registers(5), registers(6));
+    }
+
+    #[test]
+    fn restore_replays_the_same_random_numbers()
+    {
+        //RND V0 FF; RND V1 FF; RND V2 FF; RND V3 FF
+        let mut device: Chip8 = device_with(&[0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF]);
+        device.seed_rng(1);
+        let snapshot: Chip8Snapshot = device.snapshot();
+
+        step(&mut device, 4);
+        let first: Vec<Option<u8>> = (0..4).map(|x| device.register(x)).collect();
+        device.restore(&snapshot);
+        step(&mut device, 4);
+        let second: Vec<Option<u8>> = (0..4).map(|x| device.register(x)).collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn sub_and_subn_set_vf_when_there_is_no_borrow()
+    {
+        //LD V1 05; LD V2 03; SUB V1 V2; LD V3 03; LD V4 05; SUB V3 V4
+        let mut device: Chip8 = device_with(&[0x61, 0x05, 0x62, 0x03, 0x81, 0x25, 0x63, 0x03, 0x64, 0x05, 0x83, 0x45]);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x02), Some(1)));
+        step(&mut device, 3);
+        assert_eq!((device.register(3), device.register(0xF)), (Some(0xFE), Some(0)));
+
+        //LD V1 03; LD V2 05; SUBN V1 V2; LD V3 05; LD V4 03; SUBN V3 V4
+        let mut device: Chip8 = device_with(&[0x61, 0x03, 0x62, 0x05, 0x81, 0x27, 0x63, 0x05, 0x64, 0x03, 0x83, 0x47]);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x02), Some(1)));
+        step(&mut device, 3);
+        assert_eq!((device.register(3), device.register(0xF)), (Some(0xFE), Some(0)));
+    }
+
+    #[test]
+    fn shl_shifts_by_one_and_sets_vf_from_bit_7()
+    {
+        //LD V1 81; SHL V1 V1
+        let mut device: Chip8 = device_with(&[0x61, 0x81, 0x81, 0x1E]);
+        step(&mut device, 2);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x02), Some(1)));
+    }
+
+    #[test]
+    fn ld_b_stores_hundreds_tens_and_ones()
+    {
+        //LD I 300; LD V1 7B; LD B V1
+        let mut device: Chip8 = device_with(&[0xA3, 0x00, 0x61, 0x7B, 0xF1, 0x33]);
+        step(&mut device, 3);
+        assert_eq!(&device.memory[0x300..0x303], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn sknp_tests_the_key_held_in_vx()
+    {
+        //LD V1 07; SKNP V1
+        let mut device: Chip8 = device_with(&[0x61, 0x07, 0xE1, 0xA1]);
+        step(&mut device, 2);
+        assert_eq!(device.program_counter(), 0x206);
+
+        let mut device: Chip8 = device_with(&[0x61, 0x07, 0xE1, 0xA1]);
+        device.set_key(7, KeyState::Pressed);
+        step(&mut device, 2);
+        assert_eq!(device.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn drw_sets_vf_on_collision()
+    {
+        //LD F V0; DRW V0 V0 5; DRW V0 V0 5
+        let mut device: Chip8 = device_with(&[0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05]);
+        step(&mut device, 2);
+        assert_eq!(device.register(0xF), Some(0));
+        step(&mut device, 1);
+        assert_eq!(device.register(0xF), Some(1));
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn ret_returns_to_the_instruction_after_call()
+    {
+        //CALL 400, with RET at 0x400
+        let mut device: Chip8 = device_with(&[0x24, 0x00]);
+        device.write_memory(0x400, &[0x00, 0xEE]);
+        step(&mut device, 1);
+        assert_eq!(device.program_counter(), 0x400);
+        assert_eq!(device.call_stack(), vec![0x202]);
+        step(&mut device, 1);
+        assert_eq!(device.program_counter(), 0x202);
+        assert!(device.call_stack().is_empty());
+    }
+
+    #[test]
+    fn fast_forward_matches_run_frame()
+    {
+        //LD V1 3C; LD DT V1; LD F V0; RND V2 3F; DRW V2 V2 5; ADD V0 01; JP 204
+        let program: [u8; 14] = [0x61, 0x3C, 0xF1, 0x15, 0xF0, 0x29, 0xC2, 0x3F, 0xD2, 0x25, 0x70, 0x01, 0x12, 0x04];
+        let mut stepped: Chip8 = device_with(&program);
+        let mut fast:    Chip8 = device_with(&program);
+        stepped.seed_rng(3);
+        fast.seed_rng(3);
+
+        stepped.enable_frame_history(4);
+        fast.enable_frame_history(4);
+
+        for _ in 0..60
+        {
+            stepped.run_frame(DEFAULT_CYCLES_PER_FRAME);
+        }
+        fast.fast_forward(60);
+
+        assert_eq!(fast.state_hash(), stepped.state_hash());
+        assert_eq!(fast.framebuffer_bits(), stepped.framebuffer_bits());
+        assert_eq!(fast.last_sprite(), stepped.last_sprite());
+        assert_eq!(fast.timers().delay, 0);
+        for _ in 0..4
+        {
+            assert!(fast.rewind_frame());
+            assert!(stepped.rewind_frame());
+            assert_eq!(fast.state_hash(), stepped.state_hash());
+        }
+        assert!(!fast.rewind_frame());
+    }
+
+    #[test]
+    fn benchmark_ips_leaves_the_device_untouched()
+    {
+        //LD V0 01; ADD V0 01; JP 202
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02]);
+        device.load_font(&[0xFF; FONT_SIZE], 0x100).unwrap();
+        device.watch_register(0, 4);
+        device.enable_frame_history(2);
+        device.run_frame(3);
+        let hash:    u64     = device.state_hash();
+        let crc:     u32     = device.program_crc32();
+        let history: Vec<u8> = device.register_history(0).unwrap().to_vec();
+        let font:    Vec<u8> = device.font_data().to_vec();
+
+        let ips: f64 = device.benchmark_ips(std::time::Duration::from_millis(20));
+        assert!(ips.is_finite() && (ips > 0.0));
+
+        assert_eq!(device.state_hash(), hash);
+        assert_eq!(device.program_crc32(), crc);
+        assert_eq!(device.register_history(0).unwrap(), history.as_slice());
+        assert_eq!(device.font_data(), font.as_slice());
+        assert!(device.rewind_frame());
+    }
+
+    #[test]
+    fn auto_configure_quirks_applies_the_matching_profile()
+    {
+        //0xCBF43926 is the standard CRC-32 check value, the CRC of the ASCII digits "123456789".
+        let mut device: Chip8 = device_with(b"123456789");
+        assert_eq!(device.program_crc32(), 0xCBF43926);
+
+        let table: [(u32, QuirkProfile); 2] = [(0x12345678, QuirkProfile::SuperChip), (0xCBF43926, QuirkProfile::Cosmac)];
+        assert_eq!(device.auto_configure_quirks_from(&table), Some(QuirkProfile::Cosmac));
+        assert_eq!(device.quirks(), QuirkProfile::Cosmac.quirks());
+
+        let mut device: Chip8 = device_with(b"12345678");
+        assert_eq!(device.auto_configure_quirks_from(&table), None);
+        assert!(device.is_default_quirks());
+    }
+
+    #[test]
+    fn auto_configure_quirks_recognizes_the_ibm_logo()
+    {
+        //CLS; LD I 22A; LD V0 0C; LD V1 08; DRW V0 V1 F; ADD V0 09; LD I 239; DRW V0 V1 F; LD I 248; ADD V0 08; DRW V0 V1 F;
+        //ADD V0 04; LD I 257; DRW V0 V1 F; ADD V0 08; LD I 266; DRW V0 V1 F; ADD V0 08; LD I 275; DRW V0 V1 F; JP 228, followed by
+        //the six sprites making up the logo
+        let ibm_logo: [u8; 132] =
+        [
+            0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xA2, 0x39, 0xD0, 0x1F, 0xA2, 0x48, 0x70, 0x08,
+            0xD0, 0x1F, 0x70, 0x04, 0xA2, 0x57, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x66, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x75, 0xD0, 0x1F,
+            0x12, 0x28, 0xFF, 0x00, 0xFF, 0x00, 0x3C, 0x00, 0x3C, 0x00, 0x3C, 0x00, 0x3C, 0x00, 0xFF, 0x00, 0xFF, 0xFF, 0x00, 0xFF,
+            0x00, 0x38, 0x00, 0x3F, 0x00, 0x3F, 0x00, 0x38, 0x00, 0xFF, 0x00, 0xFF, 0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0x00,
+            0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0xF8, 0x00, 0xFC, 0x00, 0x3E, 0x00, 0x3F, 0x00, 0x3B, 0x00, 0x39, 0x00, 0xF8,
+            0x00, 0xF8, 0x03, 0x00, 0x07, 0x00, 0x0F, 0x00, 0xBF, 0x00, 0xFB, 0x00, 0xF3, 0x00, 0xE3, 0x00, 0x43, 0xE0, 0x00, 0xE0,
+            0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0xE0, 0x00, 0xE0
+        ];
+        let mut device: Chip8 = device_with(&ibm_logo);
+        device.set_quirks(QuirkProfile::Cosmac.quirks());
+
+        assert_eq!(device.auto_configure_quirks(), Some(QuirkProfile::Modern));
+        assert!(device.is_default_quirks());
+
+        //Any other program is left alone.
+        let mut device: Chip8 = device_with(&ibm_logo[..130]);
+        device.set_quirks(QuirkProfile::Cosmac.quirks());
+        assert_eq!(device.auto_configure_quirks(), None);
+        assert_eq!(device.quirks(), QuirkProfile::Cosmac.quirks());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_timer_methods_map_onto_the_whole_tick_timers()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_timers(TimerState { delay: 10, sound: 3 });
+
+        device.subtract_from_delaycounter(2.5);
+        device.subtract_from_delaycounter(-1.0);
+        device.subtract_from_delaycounter(f32::NAN);
+        assert_eq!(device.delay_timer_raw(), 8.0);
+
+        assert!(!device.subtract_from_buzzercounter(2.0));
+        assert!(!device.subtract_from_buzzercounter(f32::INFINITY));
+        assert!(device.subtract_from_buzzercounter(5.0));
+        assert_eq!(device.sound_timer_raw(), 0.0);
+        assert_eq!(device.timers(), TimerState { delay: 8, sound: 0 });
+
+        //Fractions of a tick add up over several calls. The 0.5 left over from 2.5 above is still carried.
+        device.subtract_from_delaycounter(0.25);
+        assert_eq!(device.timers().delay, 8);
+        device.subtract_from_delaycounter(0.25);
+        assert_eq!(device.timers().delay, 7);
+        device.subtract_from_delaycounter(0.5);
+        device.subtract_from_delaycounter(0.5);
+        assert_eq!(device.timers().delay, 6);
+
+        device.set_timers(TimerState { delay: 0, sound: 2 });
+        assert!(!device.subtract_from_buzzercounter(0.75));
+        assert!(!device.subtract_from_buzzercounter(0.75));
+        assert_eq!(device.timers().sound, 1);
+        assert!(device.subtract_from_buzzercounter(0.5));
+    }
+
+    #[test]
+    fn cycles_for_framerate_rounds_to_the_nearest_instruction()
+    {
+        assert_eq!(cycles_for_framerate(700, 60), DEFAULT_CYCLES_PER_FRAME);
+        assert_eq!(cycles_for_framerate(689, 60), 11);
+        assert_eq!(cycles_for_framerate(690, 60), 12);
+        assert_eq!(cycles_for_framerate(600, 60), 10);
+        assert_eq!(cycles_for_framerate(700, 0), 0);
+        assert_eq!(cycles_for_framerate(u32::MAX, 1), u32::MAX);
+    }
+
+    #[test]
+    fn grid_to_sprite_packs_rows_msb_first()
+    {
+        let checkerboard: [[bool; 8]; 2] =
+        [
+            [true, false, true, false, true, false, true, false],
+            [false, true, false, true, false, true, false, true]
+        ];
+        assert_eq!(grid_to_sprite(&checkerboard), vec![0xAA, 0x55]);
+        assert_eq!(grid_to_sprite(&[[true, false, false, false, false, false, false, true]]), vec![0x81]);
+        assert!(grid_to_sprite(&[]).is_empty());
+    }
+
+    #[test]
+    fn strict_memory_warns_on_sprites_read_from_the_font()
+    {
+        //LD I 050; DRW V0 V0 5
+        let mut device: Chip8 = device_with(&[0xA0, 0x50, 0xD0, 0x05]);
+        device.set_strict_memory(true);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        step(&mut device, 2);
+        assert_eq!(*warnings.borrow(), vec![Warning::SpriteFromFontRegion]);
+
+        //LD F V0; DRW V0 V0 5
+        let mut device: Chip8 = device_with(&[0xF0, 0x29, 0xD0, 0x05]);
+        device.set_strict_memory(true);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        step(&mut device, 2);
+        assert!(warnings.borrow().is_empty());
+
+        //Without strict memory, nothing is reported.
+        let mut device: Chip8 = device_with(&[0xA0, 0x50, 0xD0, 0x05]);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        step(&mut device, 2);
+        assert!(warnings.borrow().is_empty());
+    }
+
+    #[test]
+    fn only_fx29_marks_i_as_pointing_at_the_font()
+    {
+        //LD F V0; LD V1 05; ADD I V1; DRW V0 V0 5
+        let mut device: Chip8 = device_with(&[0xF0, 0x29, 0x61, 0x05, 0xF1, 0x1E, 0xD0, 0x05]);
+        device.set_strict_memory(true);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        step(&mut device, 4);
+        assert_eq!(*warnings.borrow(), vec![Warning::SpriteFromFontRegion]);
+
+        //LD F V0; DRW V0 V0 5 twice, with DRW moving I on to the next glyph after the first.
+        let mut device: Chip8 = Chip8::builder().quirks(Quirks { draw_advances_i: true, ..Quirks::default() }).build();
+        device.load_rom(&[0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05]).unwrap();
+        device.set_strict_memory(true);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        step(&mut device, 2);
+        assert!(warnings.borrow().is_empty());
+        step(&mut device, 1);
+        assert_eq!(*warnings.borrow(), vec![Warning::SpriteFromFontRegion]);
+    }
+
+    #[test]
+    fn framebuffer_bits_round_trip()
+    {
+        //LD V0 3C; LD F V0; LD V1 0B; DRW V0 V1 5
+        let mut device: Chip8 = device_with(&[0x60, 0x3C, 0xF0, 0x29, 0x61, 0x0B, 0xD0, 0x15]);
+        step(&mut device, 4);
+        let bits: Vec<u8> = device.framebuffer_bits();
+        assert_eq!(bits.len(), 64 * 32 / 8);
+
+        let mut copy: Chip8 = Chip8::builder().build();
+        assert!(copy.set_framebuffer_bits(&bits));
+        assert_eq!(screen_diff(&device, &copy), Some(Vec::new()));
+        assert_eq!(copy.framebuffer_bits(), bits);
+
+        assert!(!copy.set_framebuffer_bits(&bits[1..]));
+        assert_eq!(copy.framebuffer_bits(), bits);
+    }
+
+    #[test]
+    fn draw_advances_i_only_with_the_quirk()
+    {
+        //LD I 300; DRW V0 V0 5
+        let program: [u8; 4] = [0xA3, 0x00, 0xD0, 0x05];
+        let mut device: Chip8 = device_with(&program);
+        step(&mut device, 2);
+        assert_eq!(device.index(), 0x300);
+
+        let mut device: Chip8 = Chip8::builder().quirks(Quirks { draw_advances_i: true, ..Quirks::default() }).build();
+        device.load_rom(&program).unwrap();
+        step(&mut device, 2);
+        assert_eq!(device.index(), 0x305);
+    }
+
+    #[test]
+    fn fetching_from_an_odd_address_warns()
+    {
+        let mut device: Chip8 = device_with(&[0x00, 0x60, 0x12, 0x00]);
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        assert!(device.set_program_counter(0x201));
+        step(&mut device, 1);
+        assert_eq!(*warnings.borrow(), vec![Warning::MisalignedFetch(0x201)]);
+        assert_eq!(device.current_opcode(), 0x6012);
+
+        //The low byte of an instruction fetched from the last byte of memory wraps around to address 0.
+        let mut device: Chip8 = device_with(&[]);
+        device.set_memory_byte(0xFFF, 0x60);
+        device.set_program_counter(0xFFF);
+        step(&mut device, 1);
+        assert_eq!(device.current_opcode(), 0x6000);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn raw_timer_accessors_read_the_counters()
+    {
+        //LD V1 0A; LD DT V1; LD V2 03; LD ST V2
+        let mut device: Chip8 = device_with(&[0x61, 0x0A, 0xF1, 0x15, 0x62, 0x03, 0xF2, 0x18]);
+        step(&mut device, 4);
+        assert_eq!(device.delay_timer_raw(), 10.0);
+        assert_eq!(device.sound_timer_raw(), 3.0);
+
+        device.subtract_from_delaycounter(4.0);
+        assert_eq!(device.delay_timer_raw(), 6.0);
+    }
+
+    #[test]
+    fn instructions_since_draw_resets_on_drw_and_cls()
+    {
+        //ADD V0 01; ADD V0 01; ADD V0 01; DRW V0 V0 1; ADD V0 01; CLS
+        let mut device: Chip8 = device_with(&[0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0xD0, 0x01, 0x70, 0x01, 0x00, 0xE0]);
+        assert_eq!(device.instructions_since_draw(), 0);
+        step(&mut device, 3);
+        assert_eq!(device.instructions_since_draw(), 3);
+        step(&mut device, 1);
+        assert_eq!(device.instructions_since_draw(), 0);
+        step(&mut device, 1);
+        assert_eq!(device.instructions_since_draw(), 1);
+        step(&mut device, 1);
+        assert_eq!(device.instructions_since_draw(), 0);
+    }
+
+    #[test]
+    fn second_plane_renders_with_palette_color_2()
+    {
+        let palette: [[u8; 4]; 4] = [[0, 0, 0, 255], [1, 1, 1, 255], [2, 2, 2, 255], [3, 3, 3, 255]];
+
+        //PLANE 2; LD F V0; DRW V0 V0 5
+        let mut device: Chip8 = Chip8::builder().xo_chip(true).build();
+        device.load_rom(&[0xF2, 0x01, 0xF0, 0x29, 0xD0, 0x05]).unwrap();
+        device.set_palette(palette);
+        step(&mut device, 3);
+
+        let rgba: Vec<u8> = device.render_rgba_xochip();
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert_eq!(&rgba[0..4], &palette[2]);
+        assert_eq!(&rgba[(4 * 4)..(5 * 4)], &palette[0]);
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn set_i_is_read_back_by_every_alias()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_i(0x345);
+        assert_eq!(device.i(), 0x345);
+        assert_eq!(device.get_index(), 0x345);
+        assert_eq!(device.index(), 0x345);
+
+        device.set_i(0xF123);
+        assert_eq!(device.index(), 0x123);
+    }
+
+    #[test]
+    fn audio_loads_the_pattern_and_pitch_sets_the_pitch()
+    {
+        let pattern: [u8; 16] = [0xF0, 0x0F, 0xAA, 0x55, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+        //LD I 300; AUDIO; LD V1 70; PITCH V1
+        let mut device: Chip8 = Chip8::builder().xo_chip(true).build();
+        device.load_rom(&[0xA3, 0x00, 0xF0, 0x02, 0x61, 0x70, 0xF1, 0x3A]).unwrap();
+        device.write_memory(0x300, &pattern);
+        assert_eq!(device.audio_pitch(), 64);
+        step(&mut device, 4);
+        assert_eq!(device.audio_pattern(), pattern);
+        assert_eq!(device.audio_pitch(), 0x70);
+
+        //Outside of XO-CHIP mode both are skipped.
+        let mut device: Chip8 = device_with(&[0xA3, 0x00, 0xF0, 0x02, 0x61, 0x70, 0xF1, 0x3A]);
+        device.write_memory(0x300, &pattern);
+        step(&mut device, 4);
+        assert_eq!(device.audio_pattern(), [0; 16]);
+        assert_eq!(device.audio_pitch(), 64);
+    }
+
+    #[test]
+    fn run_budget_stops_at_a_key_wait()
+    {
+        //LD V0 01; LD V1 K
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0xF1, 0x0A]);
+        assert_eq!(device.run_budget(10), 2);
+        assert_eq!(device.cpu_state(), CpuState::WaitingForKeypress);
+        assert_eq!(device.run_budget(10), 0);
+
+        //LD V0 01; JP 200
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0x12, 0x00]);
+        assert_eq!(device.run_budget(10), 10);
+    }
+
+    #[test]
+    fn lit_pixel_count_counts_the_8_glyph()
+    {
+        //LD V0 08; LD F V0; DRW V1 V1 5. The '8' glyph is F0 90 F0 90 F0, so 4 + 2 + 4 + 2 + 4 pixels.
+        let mut device: Chip8 = device_with(&[0x60, 0x08, 0xF0, 0x29, 0xD1, 0x15]);
+        assert_eq!(device.lit_pixel_count(), 0);
+        step(&mut device, 3);
+        assert_eq!(device.lit_pixel_count(), 16);
+    }
+
+    #[test]
+    fn to_intel_hex_writes_records_with_checksums()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.write_memory(0x300, &[0x01, 0x02, 0x03]);
+        assert_eq!(device.to_intel_hex(0x300, 3), ":03030000010203F4\n:00000001FF\n");
+
+        let hex: String = device.to_intel_hex(0x300, 17);
+        let records: Vec<&str> = hex.lines().collect();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].starts_with(":10030000010203"));
+        assert_eq!(records[1], ":0103100000EC");
+
+        //The range is cut short at the end of memory.
+        assert_eq!(device.to_intel_hex(0xFFF, 4).lines().count(), 2);
+    }
+
+    #[test]
+    fn ret_on_an_empty_stack_faults_unless_lenient()
+    {
+        let mut device: Chip8 = device_with(&[0x00, 0xEE]);
+        assert_eq!(device.execute(), Some(Instruction::Ret));
+        assert_eq!(device.fault(), Some(Fault::StackUnderflow));
+        assert_eq!(device.program_counter(), 0x200);
+        assert_eq!(device.execute(), None);
+
+        let mut device: Chip8 = Chip8::builder().quirks(Quirks { lenient_stack: true, ..Quirks::default() }).build();
+        device.load_rom(&[0x00, 0xEE]).unwrap();
+        step(&mut device, 1);
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+        assert_eq!(device.stack_pointer(), 15);
+        assert_eq!(device.program_counter(), 0x002);
+    }
+
+    #[test]
+    fn last_key_wait_reports_the_register_and_key()
+    {
+        //LD V3 K
+        let mut device: Chip8 = device_with(&[0xF3, 0x0A]);
+        step(&mut device, 1);
+        assert_eq!(device.last_key_wait(), None);
+        assert_eq!(device.program_counter(), 0x200);
+
+        device.set_key(0xB, KeyState::Pressed);
+        step(&mut device, 1);
+        assert_eq!(device.last_key_wait(), Some((3, 0xB)));
+        assert_eq!(device.register(3), Some(0xB));
+        assert_eq!(device.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn run_until_draw_or_budget_stops_after_one_draw()
+    {
+        //ADD V1 01; SE V1 03; JP 200; DRW V0 V0 1; DRW V0 V0 1
+        let program: [u8; 10] = [0x71, 0x01, 0x31, 0x03, 0x12, 0x00, 0xD0, 0x01, 0xD0, 0x01];
+        let mut device: Chip8 = device_with(&program);
+        device.set_memory_byte(0x000, 0x80);
+        assert!(device.run_until_draw_or_budget(100));
+        assert_eq!(device.program_counter(), 0x208);
+        assert_eq!(device.lit_pixel_count(), 1);
+        assert!(device.run_until_draw_or_budget(100));
+        assert_eq!(device.program_counter(), 0x20A);
+        assert_eq!(device.lit_pixel_count(), 0);
+
+        let mut device: Chip8 = device_with(&program);
+        assert!(!device.run_until_draw_or_budget(3));
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn memory_layout_constants_fit_together()
+    {
+        assert!((FONT_BASE as usize) + FONT_SIZE <= (PROGRAM_BASE as usize));
+
+        let mut device: Chip8 = Chip8::builder().build();
+        let too_large: Vec<u8> = vec![0; MEMORY_SIZE - PROGRAM_BASE as usize + 1];
+        assert_eq!(device.load_rom(&too_large), Err(LoadError::TooLarge { len: too_large.len() }));
+        assert_eq!(device.load_rom(&too_large[1..]), Ok(()));
+    }
+
+    #[test]
+    fn swap_registers_exchanges_two_registers()
+    {
+        //LD V1 05; LD V2 09
+        let mut device: Chip8 = device_with(&[0x61, 0x05, 0x62, 0x09]);
+        step(&mut device, 2);
+        assert_eq!(device.swap_registers(1, 2), Ok(()));
+        assert_eq!((device.register(1), device.register(2), device.register(0xF)), (Some(9), Some(5), Some(0)));
+        assert_eq!(device.swap_registers(1, 0x10), Err(RegError::InvalidRegister(0x10)));
+        assert_eq!((device.register(1), device.register(2)), (Some(9), Some(5)));
+    }
+
+    #[test]
+    fn sub_borrows_at_the_boundaries()
+    {
+        //(Vx, Vy, Vx - Vy, VF)
+        let cases: [(u8, u8, u8, u8); 3] = [(0x00, 0x01, 0xFF, 0), (0xFF, 0xFE, 0x01, 1), (0x42, 0x42, 0x00, 1)];
+        for (vx, vy, result, vf) in cases.iter()
+        {
+            //LD V1 vx; LD V2 vy; SUB V1 V2
+            let mut device: Chip8 = device_with(&[0x61, *vx, 0x62, *vy, 0x81, 0x25]);
+            step(&mut device, 3);
+            assert_eq!((device.register(1), device.register(0xF)), (Some(*result), Some(*vf)), "{:02X} - {:02X}", vx, vy);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn save_png_writes_a_scaled_image()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_screen_pixel(2, 5, PixelState::Lit);
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("rusty-chip8-{}.png", std::process::id()));
+        device.save_png(path.to_str().unwrap(), 3).unwrap();
+
+        let png: image::GrayImage = image::open(&path).unwrap().to_luma8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(png.dimensions(), (64 * 3, 32 * 3));
+        assert_eq!(png.get_pixel(5 * 3, 2 * 3), &image::Luma([0xFF]));
+        assert_eq!(png.get_pixel(0, 0), &image::Luma([0x00]));
+    }
+
+    #[test]
+    fn reset_display_mode_returns_to_low_resolution()
+    {
+        //HIGH
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&[0x00, 0xFF]).unwrap();
+        step(&mut device, 1);
+        assert_eq!((device.resolution(), device.screen_width(), device.screen_height()), (Resolution::High, 128, 64));
+        device.set_screen_pixel(0, 100, PixelState::Lit);
+
+        device.reset_display_mode();
+        assert_eq!((device.resolution(), device.screen_width(), device.screen_height()), (Resolution::Low, 64, 32));
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn clip_sprites_drops_rows_past_the_bottom_edge()
+    {
+        //LD I 300; LD V1 1C; DRW V0 V1 A, drawing a solid 8x10 sprite at row 28.
+        let program: [u8; 6] = [0xA3, 0x00, 0x61, 0x1C, 0xD0, 0x1A];
+
+        let mut device: Chip8 = Chip8::builder().quirks(Quirks { clip_sprites: true, ..Quirks::default() }).build();
+        device.load_rom(&program).unwrap();
+        device.write_memory(0x300, &[0xFF; 10]);
+        device.set_screen_pixel(2, 0, PixelState::Lit);
+        step(&mut device, 3);
+        assert_eq!(device.register(0xF), Some(0));
+        assert_eq!(device.lit_pixel_count(), 4 * 8 + 1);
+        assert_eq!(device.lit_bounds(), Some((0, 2, 7, 31)));
+        assert_eq!(device.get_screen_pixel(2, 0), Some(PixelState::Lit));
+
+        //Without the quirk, row 34 wraps around to row 2 and collides.
+        let mut device: Chip8 = device_with(&program);
+        device.write_memory(0x300, &[0xFF; 10]);
+        device.set_screen_pixel(2, 0, PixelState::Lit);
+        step(&mut device, 3);
+        assert_eq!(device.register(0xF), Some(1));
+        assert_eq!(device.get_screen_pixel(2, 0), Some(PixelState::Unlit));
+    }
+
+    #[test]
+    fn watch_register_records_each_new_value()
+    {
+        //ADD V0 01; SE V0 04; JP 200; JP 206
+        let program: [u8; 8] = [0x70, 0x01, 0x30, 0x04, 0x12, 0x00, 0x12, 0x06];
+        let mut device: Chip8 = device_with(&program);
+        device.watch_register(0, 8);
+        step(&mut device, 20);
+        assert_eq!(device.register_history(0), Some(&[0, 1, 2, 3, 4][..]));
+        assert_eq!(device.register_history(1), None);
+
+        let mut device: Chip8 = device_with(&program);
+        device.watch_register(0, 3);
+        step(&mut device, 20);
+        assert_eq!(device.register_history(0), Some(&[2, 3, 4][..]));
+
+        device.watch_register(0, 0);
+        assert_eq!(device.register_history(0), None);
+    }
+
+    #[test]
+    fn emulated_time_counts_timer_ticks()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_timers(TimerState { delay: 0, sound: 2 });
+        assert!(!device.tick_timers());
+        assert!(device.tick_timers());
+        for _ in 0..118
+        {
+            device.tick_timers();
+        }
+
+        assert!((device.emulated_time() - 2.0).abs() < 1e-9);
+        device.soft_reset();
+        assert_eq!(device.emulated_time(), 0.0);
+    }
+
+    #[test]
+    fn save_and_load_copy_a_register_range()
+    {
+        assert_eq!(disassemble(0x5122), "SAVE 1 2");
+        assert_eq!(disassemble(0x5123), "LOAD 1 2");
+
+        //LD V1 0A; LD V2 0B; LD V3 0C; LD I 300; SAVE V3 V1; LOAD V4 V6
+        let mut device: Chip8 = Chip8::builder().xo_chip(true).build();
+        device.load_rom(&[0x61, 0x0A, 0x62, 0x0B, 0x63, 0x0C, 0xA3, 0x00, 0x53, 0x12, 0x54, 0x63]).unwrap();
+        step(&mut device, 6);
+        assert_eq!(&device.memory[0x300..0x303], &[0x0C, 0x0B, 0x0A]);
+        assert_eq!((device.register(4), device.register(5), device.register(6)), (Some(0x0C), Some(0x0B), Some(0x0A)));
+        assert_eq!(device.index(), 0x300);
+    }
+
+    #[test]
+    fn running_past_the_end_of_memory_warns()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        let warnings: Rc<RefCell<Vec<Warning>>> = collect_warnings(&mut device);
+        device.set_memory_word(0xFFC, 0x6001);
+        device.set_memory_word(0xFFE, 0x6002);
+        device.set_program_counter(0xFFC);
+        step(&mut device, 1);
+        assert!(warnings.borrow().is_empty());
+        step(&mut device, 1);
+        assert_eq!(*warnings.borrow(), vec![Warning::ProgramCounterWrapped(0xFFE)]);
+        assert_eq!(device.program_counter(), 0x000);
+    }
+
+    #[test]
+    fn mode_switches_scale_the_screen_without_clear_on_mode_switch()
+    {
+        //HIGH; LOW
+        let program: [u8; 4] = [0x00, 0xFF, 0x00, 0xFE];
+
+        let mut device: Chip8 = Chip8::builder().super_chip(true).quirks(Quirks { clear_on_mode_switch: false, ..Quirks::default() }).build();
+        device.load_rom(&program).unwrap();
+        device.set_screen_pixel(3, 5, PixelState::Lit);
+        step(&mut device, 1);
+        assert_eq!(device.lit_pixel_count(), 4);
+        assert_eq!(device.lit_bounds(), Some((10, 6, 11, 7)));
+        step(&mut device, 1);
+        assert_eq!(device.lit_pixel_count(), 1);
+        assert_eq!(device.get_screen_pixel(3, 5), Some(PixelState::Lit));
+
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&program).unwrap();
+        device.set_screen_pixel(3, 5, PixelState::Lit);
+        step(&mut device, 1);
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn call_stack_lists_nested_calls_outermost_first()
+    {
+        //CALL 300, with CALL 400 at 0x300 and RET at 0x400
+        let mut device: Chip8 = device_with(&[0x23, 0x00]);
+        device.write_memory(0x300, &[0x24, 0x00]);
+        device.write_memory(0x400, &[0x00, 0xEE]);
+        step(&mut device, 2);
+        assert_eq!(device.call_stack(), vec![0x202, 0x302]);
+        step(&mut device, 1);
+        assert_eq!(device.call_stack(), vec![0x202]);
+        assert_eq!(device.program_counter(), 0x302);
+    }
+
+    #[test]
+    fn drw_matches_drawing_pixel_by_pixel()
+    {
+        let sprite: [u8; 5] = [0xF1, 0x80, 0x3C, 0xFF, 0x81];
+        let positions: [(u8, u8); 5] = [(0, 0), (10, 5), (60, 3), (30, 29), (250, 200)];
+
+        //LD I 300; LD V0 x; LD V1 y; DRW V0 V1 5, for each position in turn
+        let mut program: Vec<u8> = vec![0xA3, 0x00];
+        for (x, y) in positions.iter()
+        {
+            program.extend_from_slice(&[0x60, *x, 0x61, *y, 0xD0, 0x15]);
+        }
+        let mut device: Chip8 = device_with(&program);
+        device.write_memory(0x300, &sprite);
+        step(&mut device, 1 + 3 * positions.len());
+
+        let mut reference: Chip8 = Chip8::builder().build();
+        for (x, y) in positions.iter()
+        {
+            for (row, byte) in sprite.iter().enumerate()
+            {
+                for bit in 0..8
+                {
+                    if (byte & (0x80 >> bit)) == 0
+                    {
+                        continue;
+                    }
+
+                    let pixel_row: u8 = (((*y as usize) % 32 + row) % 32) as u8;
+                    let pixel_col: u8 = (((*x as usize) % 64 + bit) % 64) as u8;
+                    let toggled: PixelState = match reference.get_screen_pixel(pixel_row, pixel_col)
+                    {
+                        Some(PixelState::Lit) => PixelState::Unlit,
+                        _                     => PixelState::Lit
+                    };
+                    reference.set_screen_pixel(pixel_row, pixel_col, toggled);
+                }
+            }
+        }
+
+        assert_eq!(screen_diff(&device, &reference), Some(Vec::new()));
+        assert!(device.lit_pixel_count() > 0);
+    }
+
+    #[test]
+    fn last_vf_source_reports_the_instruction_that_set_vf()
+    {
+        //LD V1 F0; LD V2 20; ADD V1 V2; LD V3 02; SHR V3 V3
+        let mut device: Chip8 = device_with(&[0x61, 0xF0, 0x62, 0x20, 0x81, 0x24, 0x63, 0x02, 0x83, 0x36]);
+        step(&mut device, 2);
+        assert_eq!(device.last_vf_source(), None);
+        step(&mut device, 1);
+        assert_eq!(device.last_vf_source(), Some(VfSource::Add));
+        step(&mut device, 1);
+        assert_eq!(device.last_vf_source(), Some(VfSource::Add));
+        step(&mut device, 1);
+        assert_eq!(device.last_vf_source(), Some(VfSource::Shr));
+    }
+
+    #[test]
+    fn frame_pokes_override_writes_made_during_the_frame()
+    {
+        //LD I 300; LD V0 11; LD [I] V0; JP 206
+        let mut device: Chip8 = device_with(&[0xA3, 0x00, 0x60, 0x11, 0xF0, 0x55, 0x12, 0x06]);
+        device.add_frame_poke(0x300, 0x99);
+        device.add_frame_poke(0x1000, 0x99);
+        device.run_frame(3);
+        assert_eq!(device.memory[0x300], 0x99);
+
+        device.clear_frame_pokes();
+        device.memory[0x300] = 0x00;
+        device.run_frame(2);
+        assert_eq!(device.memory[0x300], 0x00);
+    }
+
+    #[test]
+    fn timers_round_trip_through_set_timers()
+    {
+        //LD V0 DT
+        let mut device: Chip8 = device_with(&[0xF0, 0x07]);
+        device.set_timers(TimerState { delay: 7, sound: 9 });
+        assert_eq!(device.timers(), TimerState { delay: 7, sound: 9 });
+        step(&mut device, 1);
+        assert_eq!(device.register(0), Some(7));
+    }
+
+    #[test]
+    fn start_hires_survives_a_hard_reset()
+    {
+        let mut device: Chip8 = Chip8::builder().super_chip(true).start_hires(true).build();
+        assert!(device.is_hires());
+        device.reset_display_mode();
+        assert!(!device.is_hires());
+
+        device.hard_reset();
+        assert!(device.is_hires());
+        assert_eq!((device.screen_width(), device.screen_height()), (128, 64));
+        assert!(!Chip8::builder().build().is_hires());
+    }
+
+    #[test]
+    fn last_sprite_holds_the_position_and_data_of_the_last_drw()
+    {
+        //LD V0 05; LD V1 07; LD F V2; DRW V0 V1 5
+        let mut device: Chip8 = device_with(&[0x60, 0x05, 0x61, 0x07, 0xF2, 0x29, 0xD0, 0x15]);
+        step(&mut device, 3);
+        assert_eq!(device.last_sprite(), None);
+        step(&mut device, 1);
+        assert_eq!(device.last_sprite(), Some((5, 7, vec![0xF0, 0x90, 0x90, 0x90, 0xF0])));
+    }
+
+    #[test]
+    fn call_with_a_full_stack_faults_without_overwriting_it()
+    {
+        //CALL 200
+        let mut device: Chip8 = device_with(&[0x22, 0x00]);
+        step(&mut device, 16);
+        assert_eq!(device.stack_pointer(), 16);
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+
+        step(&mut device, 1);
+        assert_eq!(device.fault(), Some(Fault::StackOverflow));
+        assert_eq!(device.stack_pointer(), 16);
+        assert_eq!(device.call_stack(), vec![0x202; 16]);
+    }
+
+    #[test]
+    fn disassemble_window_centers_on_the_program_counter()
+    {
+        //LD V0 01; LD V1 02; CLS; JP 200
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0x61, 0x02, 0x00, 0xE0, 0x12, 0x00]);
+        step(&mut device, 2);
+        assert_eq!(device.disassemble_window(2, 1), vec![(0x200, 0x6001, String::from("LD 0 1"), false),
+                                                         (0x202, 0x6102, String::from("LD 1 2"), false),
+                                                         (0x204, 0x00E0, String::from("CLS"), true),
+                                                         (0x206, 0x1200, String::from("JP 200"), false)]);
+
+        //The window is cut short at the start of memory.
+        let device: Chip8 = device_with(&[]);
+        assert_eq!(device.disassemble_window(0x200, 0).len(), 0x101);
+    }
+
+    #[test]
+    fn key_labels_default_to_hex_digits()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        assert_eq!(device.key_label(0xA), Some("A"));
+        assert!(device.set_key_label(0, "Start"));
+        assert_eq!(device.key_label(0), Some("Start"));
+        assert_eq!(device.key_label(1), Some("1"));
+        assert!(!device.set_key_label(16, "Nothing"));
+        assert_eq!(device.key_label(16), None);
+    }
+
+    #[test]
+    fn the_opcode_field_holds_the_instruction_that_just_ran()
+    {
+        //CALL 206; LD V1 23; RET
+        let mut device: Chip8 = device_with(&[0x22, 0x06, 0x61, 0x23, 0x00, 0x00, 0x00, 0xEE]);
+        let expected: [u16; 3] = [0x2206, 0x00EE, 0x6123];
+        for opcode in expected.iter()
+        {
+            step(&mut device, 1);
+            assert_eq!(device.opcode, *opcode);
+        }
+    }
+
+    #[test]
+    fn run_frame_rgba_renders_the_screen_after_the_frame()
+    {
+        let on:  [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+        let off: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+        //LD F V0; DRW V0 V0 5; HIGH
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&[0xF0, 0x29, 0xD0, 0x05, 0x00, 0xFF]).unwrap();
+        let rgba: Vec<u8> = device.run_frame_rgba(2, on, off);
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert_eq!(&rgba[0..4], &on);
+        assert_eq!(&rgba[(4 * 4)..(5 * 4)], &off);
+        assert_eq!(&rgba[(64 * 4)..(65 * 4)], &on);
+
+        let rgba: Vec<u8> = device.run_frame_rgba(1, on, off);
+        assert_eq!(rgba.len(), 128 * 64 * 4);
+    }
+
+    #[test]
+    fn add_wraps_and_sets_vf_on_carry()
+    {
+        //LD V1 F0; LD V2 20; ADD V1 V2
+        let mut device: Chip8 = device_with(&[0x61, 0xF0, 0x62, 0x20, 0x81, 0x24]);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x10), Some(1)));
+
+        //LD V1 10; LD V2 20; ADD V1 V2
+        let mut device: Chip8 = device_with(&[0x61, 0x10, 0x62, 0x20, 0x81, 0x24]);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x30), Some(0)));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn negative_and_nan_timer_decrements_are_ignored()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_timers(TimerState { delay: 5, sound: 5 });
+        device.subtract_from_delaycounter(-1.0);
+        device.subtract_from_delaycounter(f32::NAN);
+        assert!(!device.subtract_from_buzzercounter(-1.0));
+        assert!(!device.subtract_from_buzzercounter(f32::NAN));
+        assert_eq!(device.timers(), TimerState { delay: 5, sound: 5 });
+    }
+
+    #[test]
+    fn shl_clears_vf_when_bit_7_is_clear()
+    {
+        //LD VF 01; LD V1 40; SHL V1 V1
+        let mut device: Chip8 = device_with(&[0x6F, 0x01, 0x61, 0x40, 0x81, 0x1E]);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x80), Some(0)));
+    }
+
+    #[test]
+    fn executed_opcode_kinds_lists_each_kind_once()
+    {
+        //LD V0 01; ADD V0 01; DRW V0 V0 1; LD V1 02; ADD V1 V0
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0x70, 0x01, 0xD0, 0x01, 0x61, 0x02, 0x81, 0x04]);
+        step(&mut device, 4);
+        assert_eq!(device.executed_opcode_kinds(), vec!["LD", "ADD", "DRW"]);
+        step(&mut device, 1);
+        assert_eq!(device.executed_opcode_kinds(), vec!["LD", "ADD", "DRW"]);
+
+        device.soft_reset();
+        assert!(device.executed_opcode_kinds().is_empty());
+    }
+
+    #[test]
+    fn skp_and_sknp_treat_keys_past_f_as_unpressed()
+    {
+        //LD V1 20; SKP V1
+        let mut device: Chip8 = device_with(&[0x61, 0x20, 0xE1, 0x9E]);
+        step(&mut device, 2);
+        assert_eq!(device.program_counter(), 0x204);
+
+        //LD V1 20; SKNP V1
+        let mut device: Chip8 = device_with(&[0x61, 0x20, 0xE1, 0xA1]);
+        step(&mut device, 2);
+        assert_eq!(device.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn load_rom_rejects_programs_that_do_not_fit()
+    {
+        let mut device: Chip8 = device_with(&[0x12, 0x34]);
+        assert_eq!(&device.memory[0x200..0x202], &[0x12, 0x34]);
+        let crc: u32 = device.program_crc32();
+
+        let oversized: Vec<u8> = vec![0xAB; MEMORY_SIZE - (PROGRAM_BASE as usize) + 1];
+        assert_eq!(device.load_rom(&oversized), Err(LoadError::TooLarge { len: oversized.len() }));
+        assert_eq!(&device.memory[0x200..0x202], &[0x12, 0x34]);
+        assert!(device.memory[0x202..MEMORY_SIZE].iter().all(|byte| *byte == 0));
+        assert_eq!(device.program_crc32(), crc);
+
+        assert_eq!(device.load_rom(&oversized[1..]), Ok(()));
+        assert_eq!(device.memory[MEMORY_SIZE - 1], 0xAB);
+    }
+
+    #[test]
+    fn seek_to_instruction_matches_stepping()
+    {
+        //RND V0 FF; ADD V1 V0; LD F V1; DRW V1 V0 5; JP 200
+        let program: [u8; 10] = [0xC0, 0xFF, 0x81, 0x04, 0xF1, 0x29, 0xD1, 0x05, 0x12, 0x00];
+
+        let mut stepped: Chip8 = device_with(&program);
+        stepped.seed_rng(9);
+        step(&mut stepped, 50);
+
+        let mut sought: Chip8 = device_with(&program);
+        sought.seed_rng(9);
+        sought.seek_to_instruction(50);
+        assert_eq!(sought.state_hash(), stepped.state_hash());
+
+        sought.seek_to_instruction(80);
+        sought.seek_to_instruction(50);
+        assert_eq!(sought.instructions_executed(), 50);
+        assert_eq!(sought.state_hash(), stepped.state_hash());
+    }
+
+    #[test]
+    fn drain_key_events_returns_each_change_once()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_key(5, KeyState::Pressed);
+        device.set_key(5, KeyState::Pressed);
+        device.set_key(5, KeyState::Unpressed);
+        assert_eq!(device.drain_key_events(), vec![(5, KeyState::Pressed), (5, KeyState::Unpressed)]);
+        assert!(device.drain_key_events().is_empty());
+    }
+
+    #[test]
+    fn current_opcode_returns_the_last_fetched_instruction()
+    {
+        //LD I 123
+        let mut device: Chip8 = device_with(&[0xA1, 0x23]);
+        assert_eq!(device.current_opcode(), 0);
+        step(&mut device, 1);
+        assert_eq!(device.current_opcode(), 0xA123);
+    }
+
+    #[test]
+    fn accessors_read_the_cpu_state()
+    {
+        //LD VF 42; CALL 300
+        let mut device: Chip8 = device_with(&[0x6F, 0x42, 0x23, 0x00]);
+        step(&mut device, 2);
+        assert_eq!(device.register(0xF), Some(0x42));
+        assert_eq!(device.register(0x10), None);
+        assert_eq!(device.stack_pointer(), 1);
+        assert_eq!(device.stack()[0], 0x202);
+        assert_eq!(device.program_counter(), 0x300);
+
+        assert!(device.set_program_counter(0x456));
+        assert_eq!(device.program_counter(), 0x456);
+    }
+
+    #[test]
+    fn screen_diff_lists_the_pixels_that_differ()
+    {
+        let mut a: Chip8 = Chip8::builder().build();
+        let mut b: Chip8 = Chip8::builder().build();
+        a.set_screen_pixel(0, 0, PixelState::Lit);
+        b.set_screen_pixel(0, 0, PixelState::Lit);
+        assert_eq!(screen_diff(&a, &b), Some(Vec::new()));
+
+        b.set_screen_pixel(4, 9, PixelState::Lit);
+        assert_eq!(screen_diff(&a, &b), Some(vec![(4, 9)]));
+        assert_eq!(screen_diff(&b, &a), Some(vec![(4, 9)]));
+
+        let hires: Chip8 = Chip8::builder().start_hires(true).build();
+        assert_eq!(screen_diff(&a, &hires), None);
+        assert_eq!(screen_diff(&hires, &a), None);
+    }
+
+    #[test]
+    fn ld_b_stores_the_digits_of_255()
+    {
+        //LD I 300; LD V1 FF; LD B V1
+        let mut device: Chip8 = device_with(&[0xA3, 0x00, 0x61, 0xFF, 0xF1, 0x33]);
+        step(&mut device, 3);
+        assert_eq!(&device.memory[0x300..0x303], &[2, 5, 5]);
+    }
+
+    #[test]
+    fn xo_chip_runs_past_the_12_bit_address_range()
+    {
+        //LD V0 01 at 0xFFE, then LD V1 42 at 0x1000
+        let mut device: Chip8 = Chip8::builder().xo_chip(true).build();
+        device.set_memory_word(0xFFE, 0x6001);
+        device.set_memory_word(0x1000, 0x6142);
+        assert!(device.set_program_counter(0xFFE));
+        step(&mut device, 2);
+        assert_eq!(device.register(1), Some(0x42));
+        assert_eq!(device.program_counter(), 0x1002);
+
+        let mut device: Chip8 = Chip8::builder().build();
+        assert!(!device.set_program_counter(0x1000));
+        assert!(!device.set_memory_word(0x1000, 0x6142));
+    }
+
+    #[test]
+    fn framebuffer_packs_eight_pixels_per_byte()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_screen_pixel(0, 0, PixelState::Lit);
+        device.set_screen_pixel(1, 0, PixelState::Lit);
+        device.set_screen_pixel(31, 63, PixelState::Lit);
+
+        let framebuffer: [u8; 256] = device.framebuffer();
+        assert_eq!(framebuffer[0], 0x80);
+        assert_eq!(framebuffer[8], 0x80);
+        assert_eq!(framebuffer[255], 0x01);
+        assert_eq!(framebuffer.iter().map(|byte| byte.count_ones()).sum::<u32>(), 3);
+        assert_eq!(framebuffer.to_vec(), device.framebuffer_bits());
+
+        //In high resolution, pixels outside the top-left 64x32 region are left out.
+        let mut hires: Chip8 = Chip8::builder().start_hires(true).build();
+        hires.set_screen_pixel(1, 0, PixelState::Lit);
+        hires.set_screen_pixel(0, 64, PixelState::Lit);
+        hires.set_screen_pixel(32, 0, PixelState::Lit);
+        let framebuffer: [u8; 256] = hires.framebuffer();
+        assert_eq!(framebuffer[8], 0x80);
+        assert_eq!(framebuffer.iter().map(|byte| byte.count_ones()).sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn is_default_quirks_notices_a_changed_quirk()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        assert!(device.is_default_quirks());
+        device.set_quirks(Quirks { clip_sprites: true, ..Quirks::default() });
+        assert!(!device.is_default_quirks());
+        device.set_quirks(Quirks::default());
+        assert!(device.is_default_quirks());
+    }
+
+    #[test]
+    fn shift_uses_vy_shifts_a_copy_of_vy()
+    {
+        //LD V1 04; LD V2 81; SHR V1 V2; LD V3 04; SHL V3 V2
+        let program: [u8; 10] = [0x61, 0x04, 0x62, 0x81, 0x81, 0x26, 0x63, 0x04, 0x83, 0x2E];
+
+        let mut device: Chip8 = device_with(&program);
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x02), Some(0)));
+        step(&mut device, 2);
+        assert_eq!((device.register(3), device.register(0xF)), (Some(0x08), Some(0)));
+
+        let mut device: Chip8 = Chip8::builder().quirks(Quirks { shift_uses_vy: true, ..Quirks::default() }).build();
+        device.load_rom(&program).unwrap();
+        step(&mut device, 3);
+        assert_eq!((device.register(1), device.register(0xF)), (Some(0x40), Some(1)));
+        step(&mut device, 2);
+        assert_eq!((device.register(3), device.register(0xF)), (Some(0x02), Some(1)));
+        assert_eq!(device.register(2), Some(0x81));
+    }
+
+    #[test]
+    fn hires_mode_widens_the_screen_and_draws_16x16_sprites()
+    {
+        //HIGH; LD I 300; DRW V0 V0 0
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&[0x00, 0xFF, 0xA3, 0x00, 0xD0, 0x00]).unwrap();
+        device.write_memory(0x300, &[0xFF; 32]);
+        assert_eq!(device.get_screen_pixel(31, 63), Some(PixelState::Unlit));
+        assert_eq!(device.get_screen_pixel(32, 0), None);
+        assert_eq!(device.get_screen_pixel(0, 64), None);
+
+        step(&mut device, 1);
+        assert_eq!(device.get_screen_pixel(63, 127), Some(PixelState::Unlit));
+        assert_eq!(device.get_screen_pixel(64, 0), None);
+        assert_eq!(device.get_screen_pixel(0, 128), None);
+
+        step(&mut device, 2);
+        assert_eq!(device.lit_pixel_count(), 16 * 16);
+        assert_eq!(device.lit_bounds(), Some((0, 0, 15, 15)));
+    }
+
+    #[test]
+    fn rewind_frame_returns_to_the_end_of_the_previous_frame()
+    {
+        //ADD V0 01; JP 200
+        let mut device: Chip8 = device_with(&[0x70, 0x01, 0x12, 0x00]);
+        assert!(!device.rewind_frame());
+        device.enable_frame_history(4);
+        device.run_frame(3);
+        device.run_frame(3);
+        let second_frame: u64 = device.state_hash();
+        device.run_frame(3);
+        assert_ne!(device.state_hash(), second_frame);
+
+        assert!(device.rewind_frame());
+        assert_eq!(device.state_hash(), second_frame);
+        assert_eq!(device.register(0), Some(3));
+    }
+
+    #[test]
+    fn cpu_state_waits_for_a_keypress()
+    {
+        //LD V0 K
+        let mut device: Chip8 = device_with(&[0xF0, 0x0A]);
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+        step(&mut device, 1);
+        assert_eq!(device.cpu_state(), CpuState::WaitingForKeypress);
+        step(&mut device, 1);
+        assert_eq!(device.cpu_state(), CpuState::WaitingForKeypress);
+
+        device.set_key(4, KeyState::Pressed);
+        step(&mut device, 1);
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+    }
+
+    #[test]
+    fn scroll_opcodes_move_the_screen_and_blank_the_exposed_edge()
+    {
+        //SCD 3; SCR; SCL; SCL
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&[0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFC]).unwrap();
+        device.set_screen_pixel(0, 2, PixelState::Lit);
+        device.set_screen_pixel(30, 62, PixelState::Lit);
+
+        step(&mut device, 1);
+        assert_eq!(device.lit_bounds(), Some((2, 3, 2, 3)));
+        step(&mut device, 1);
+        assert_eq!(device.lit_bounds(), Some((6, 3, 6, 3)));
+        step(&mut device, 1);
+        assert_eq!(device.lit_bounds(), Some((2, 3, 2, 3)));
+        step(&mut device, 1);
+        assert_eq!(device.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn rnd_masks_the_bytes_of_a_custom_generator()
+    {
+        struct FixedRng
+        {
+            bytes: Vec<u8>
+        }
+
+        impl Chip8Rng for FixedRng
+        {
+            fn next_u8(&mut self) -> u8
+            {
+                self.bytes.remove(0)
+            }
+        }
+
+        //RND V0 0F; RND V1 F0; RND V2 FF
+        let mut device: Chip8 = device_with(&[0xC0, 0x0F, 0xC1, 0xF0, 0xC2, 0xFF]);
+        device.set_rng(Box::new(FixedRng { bytes: vec![0xAB, 0xCD, 0xEF] }));
+        step(&mut device, 3);
+        assert_eq!((device.register(0), device.register(1), device.register(2)), (Some(0x0B), Some(0xC0), Some(0xEF)));
+    }
+
+    #[test]
+    fn lit_bounds_boxes_every_lit_pixel()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        assert_eq!(device.lit_bounds(), None);
+        device.set_screen_pixel(4, 3, PixelState::Lit);
+        device.set_screen_pixel(20, 10, PixelState::Lit);
+        assert_eq!(device.lit_bounds(), Some((3, 4, 10, 20)));
+    }
+
+    #[test]
+    fn delay_timer_counts_down_once_per_frame()
+    {
+        //LD V0 05; LD DT V0; JP 204
+        let mut device: Chip8 = device_with(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        let mut delays: Vec<u8> = Vec::new();
+        for _ in 0..7
+        {
+            device.run_frame(DEFAULT_CYCLES_PER_FRAME);
+            delays.push(device.timers().delay);
+        }
+
+        assert_eq!(delays, vec![4, 3, 2, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn speed_multiplier_scales_the_instructions_per_frame()
+    {
+        //ADD V0 01; JP 200
+        let mut device: Chip8 = device_with(&[0x70, 0x01, 0x12, 0x00]);
+        device.run_frame(10);
+        assert_eq!(device.instructions_executed(), 10);
+
+        device.set_speed_multiplier(2.0);
+        device.set_speed_multiplier(-1.0);
+        device.set_speed_multiplier(f32::NAN);
+        assert_eq!(device.speed_multiplier(), 2.0);
+        device.run_frame(10);
+        assert_eq!(device.instructions_executed(), 30);
+        assert_eq!(device.emulated_time(), 2.0 / 60.0);
+    }
+
+    #[test]
+    fn font_data_returns_the_default_font()
+    {
+        let device: Chip8 = Chip8::builder().build();
+        assert_eq!(device.font_data().len(), FONT_SIZE);
+        assert_eq!(&device.font_data()[..5], &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(&device.font_data()[75..], &[0xF0, 0x80, 0xF0, 0x80, 0x80]);
+    }
+
+    #[test]
+    fn try_set_program_counter_rejects_addresses_past_memory()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        assert_eq!(device.try_set_program_counter(5000), Err(AddressError::OutOfRange(5000)));
+        assert_eq!(device.program_counter(), 0x200);
+        assert_eq!(device.try_set_program_counter(0xFFF), Ok(()));
+        assert_eq!(device.program_counter(), 0xFFF);
+    }
+
+    #[test]
+    fn is_beeping_follows_the_sound_timer()
+    {
+        //LD V0 02; LD ST V0
+        let mut device: Chip8 = device_with(&[0x60, 0x02, 0xF0, 0x18]);
+        assert!(!device.is_beeping());
+        step(&mut device, 2);
+        assert!(device.is_beeping());
+        device.tick_timers();
+        assert!(device.is_beeping());
+        device.tick_timers();
+        assert!(!device.is_beeping());
+    }
+
+    #[test]
+    fn cancel_key_wait_writes_the_value_and_continues()
+    {
+        //LD V2 K
+        let mut device: Chip8 = device_with(&[0xF2, 0x0A]);
+        device.cancel_key_wait(0x11);
+        assert_eq!(device.register(2), Some(0));
+
+        step(&mut device, 1);
+        device.cancel_key_wait(0xFF);
+        assert_eq!(device.register(2), Some(0xFF));
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+        assert_eq!(device.program_counter(), 0x202);
+        assert_eq!(device.last_key_wait(), None);
+    }
+
+    #[test]
+    fn variant_reports_the_widest_enabled_instruction_set()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        assert_eq!(device.variant(), Variant::Chip8);
+        device.set_super_chip(true);
+        assert_eq!(device.variant(), Variant::SuperChip);
+        device.set_xo_chip(true);
+        assert_eq!(device.variant(), Variant::XoChip);
+        device.set_super_chip(false);
+        assert_eq!(device.variant(), Variant::XoChip);
+    }
+
+    #[test]
+    fn collisions_this_frame_resets_every_frame()
+    {
+        //LD F V0; DRW V0 V0 5 four times, colliding on the second and fourth; JP 20C
+        let mut device: Chip8 = device_with(&[0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x0A]);
+        device.run_frame(6);
+        assert_eq!(device.collisions_this_frame(), 2);
+        device.run_frame(6);
+        assert_eq!(device.collisions_this_frame(), 0);
+    }
+
+    #[test]
+    fn the_00nn_family_is_told_apart_by_the_low_byte()
+    {
+        //HIGH; SYS 123; EXIT
+        let program: [u8; 6] = [0x00, 0xFF, 0x01, 0x23, 0x00, 0xFD];
+
+        let mut device: Chip8 = Chip8::builder().super_chip(true).build();
+        device.load_rom(&program).unwrap();
+        assert_eq!(device.execute(), Some(Instruction::High));
+        assert!(device.is_hires());
+        assert_eq!(device.execute(), Some(Instruction::Sys(0x123)));
+        assert_eq!(device.program_counter(), 0x204);
+        step(&mut device, 1);
+        assert_eq!(device.cpu_state(), CpuState::Halted);
+
+        //Outside of Super-CHIP mode they are treated as SYS.
+        let mut device: Chip8 = device_with(&program);
+        step(&mut device, 3);
+        assert!(!device.is_hires());
+        assert_eq!(device.cpu_state(), CpuState::Ready);
+        assert_eq!(device.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn sound_end_in_frames_counts_down_with_the_sound_timer()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_timers(TimerState { delay: 0, sound: 15 });
+        assert_eq!(device.sound_end_in_frames(), 15);
+        for _ in 0..5
+        {
+            device.tick_timers();
+        }
+        assert_eq!(device.sound_end_in_frames(), 10);
+    }
+
+    #[test]
+    fn execute_returns_each_instruction_it_runs()
+    {
+        //LD V0 01; SE V0 01; CLS; RET
+        let mut device: Chip8 = device_with(&[0x60, 0x01, 0x30, 0x01, 0x00, 0xE0, 0x00, 0xEE]);
+        let executed: Vec<Option<Instruction>> = (0..4).map(|_| device.execute()).collect();
+        assert_eq!(executed, vec![Some(Instruction::LdVx {x: 0, kk: 1}), Some(Instruction::SeVx {x: 0, kk: 1}), Some(Instruction::Ret), None]);
+    }
+
+    #[test]
+    fn debounced_keys_change_after_the_window()
+    {
+        let mut device: Chip8 = Chip8::builder().build();
+        device.set_key_debounce(3);
+        device.set_key(1, KeyState::Pressed);
+        device.tick_timers();
+        device.tick_timers();
+        device.set_key(1, KeyState::Unpressed);
+        device.set_key(1, KeyState::Pressed);
+        device.tick_timers();
+        device.tick_timers();
+        assert_eq!(device.keypad[1], KeyState::Unpressed);
+        device.tick_timers();
+        assert_eq!(device.keypad[1], KeyState::Pressed);
+
+        //Chatter that settles back into the old state never reaches the keypad.
+        device.set_key(1, KeyState::Unpressed);
+        device.tick_timers();
+        device.set_key(1, KeyState::Pressed);
+        for _ in 0..5
+        {
+            device.tick_timers();
+        }
+        assert_eq!(device.keypad[1], KeyState::Pressed);
+        assert_eq!(device.drain_key_events(), vec![(1, KeyState::Pressed)]);
+    }
+
+    #[test]
+    fn state_hash_covers_the_last_vf_source_and_sprite()
+    {
+        //LD F V0; DRW V0 V0 5
+        let mut device: Chip8 = device_with(&[0xF0, 0x29, 0xD0, 0x05]);
+        step(&mut device, 2);
+        let hash: u64 = device.state_hash();
+
+        device.last_sprite = Some((0, 0, vec![0xF0]));
+        assert_ne!(device.state_hash(), hash);
+        device.last_sprite = None;
+        device.last_vf_source = None;
+        let without_sprite: u64 = device.state_hash();
+        device.last_vf_source = Some(VfSource::Draw);
+        assert_ne!(device.state_hash(), without_sprite);
+        assert_ne!(device.state_hash(), hash);
+    }
+
+    #[test]
+    fn restore_returns_to_the_exact_snapshot()
+    {
+        //RND V0 FF; ADD V1 V0; LD F V1; DRW V1 V0 5; JP 200
+        let mut device: Chip8 = device_with(&[0xC0, 0xFF, 0x81, 0x04, 0xF1, 0x29, 0xD1, 0x05, 0x12, 0x00]);
+        device.set_key_debounce(2);
+        device.run_frame(7);
+        device.set_key(3, KeyState::Pressed);
+        let snapshot: Chip8Snapshot = device.snapshot();
+        let hash: u64 = device.state_hash();
+
+        device.run_frame(7);
+        device.run_frame(7);
+        assert_eq!(device.keypad[3], KeyState::Pressed);
+        device.restore(&snapshot);
+        assert_eq!(device.snapshot(), snapshot);
+        assert_eq!(device.state_hash(), hash);
+
+        //The key change still waiting out the debounce period comes back with the snapshot.
+        assert_eq!(device.keypad[3], KeyState::Unpressed);
+        device.tick_timers();
+        device.tick_timers();
+        assert_eq!(device.keypad[3], KeyState::Pressed);
+    }
+
+    #[test]
+    fn reachable_code_follows_calls_and_skips_data()
+    {
+        //CALL 206; JP 202; data; LD V0 01; RET
+        let device: Chip8 = device_with(&[0x22, 0x06, 0x12, 0x02, 0xFF, 0xFF, 0x60, 0x01, 0x00, 0xEE]);
+        let reachable: Vec<u16> = device.reachable_code(0x200).into_iter().collect();
+        assert_eq!(reachable, vec![0x200, 0x202, 0x206, 0x208]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshots_survive_a_bincode_round_trip()
+    {
+        //LD V0 07; LD F V0; DRW V0 V0 5; CALL 200
+        let mut device: Chip8 = device_with(&[0x60, 0x07, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x00]);
+        device.run_frame(9);
+        let snapshot: Chip8Snapshot = device.snapshot();
+        let bytes: Vec<u8> = bincode::serialize(&snapshot).unwrap();
+        let decoded: Chip8Snapshot = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let mut restored: Chip8 = Chip8::builder().build();
+        restored.restore(&decoded);
+        assert_eq!(restored.state_hash(), device.state_hash());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshots_that_restore_would_panic_on_are_rejected()
+    {
+        let device: Chip8 = device_with(&[0x60, 0x07]);
+        let snapshot: Chip8Snapshot = device.snapshot();
+        let is_rejected = |damaged: &Chip8Snapshot| -> bool
+        {
+            let bytes: Vec<u8> = bincode::serialize(damaged).unwrap();
+            bincode::deserialize::<Chip8Snapshot>(&bytes).is_err()
+        };
+        assert!(!is_rejected(&snapshot));
+
+        let mut damaged: Chip8Snapshot = snapshot.clone();
+        damaged.memory.pop();
+        assert!(is_rejected(&damaged));
+
+        let mut damaged: Chip8Snapshot = snapshot.clone();
+        damaged.memory.resize(XO_CHIP_MEMORY_SIZE + 1, 0);
+        assert!(is_rejected(&damaged));
+
+        let mut damaged: Chip8Snapshot = snapshot.clone();
+        damaged.screen.push(0);
+        assert!(is_rejected(&damaged));
+
+        let mut damaged: Chip8Snapshot = snapshot.clone();
+        damaged.second_plane.clear();
+        assert!(is_rejected(&damaged));
+
+        let mut damaged: Chip8Snapshot = snapshot.clone();
+        damaged.stack_pointer = 17;
+        assert!(is_rejected(&damaged));
+
+        let mut damaged: Chip8Snapshot = snapshot;
+        damaged.temp_vx = 0x10;
+        assert!(is_rejected(&damaged));
+    }
+
+    #[test]
+    fn every_f_series_instruction_counts_once()
+    {
+        let opcodes: [u16; 12] = [0xF107, 0xF10A, 0xF115, 0xF118, 0xF11E, 0xF129, 0xF133, 0xF155, 0xF165, 0xF201, 0xF002, 0xF13A];
+        for opcode in opcodes.iter()
+        {
+            let mut device: Chip8 = Chip8::builder().xo_chip(true).build();
+            device.load_rom(&[(opcode >> 8) as u8, (opcode & 0xFF) as u8]).unwrap();
+            step(&mut device, 1);
+            assert_eq!(device.instructions_executed(), 1, "{:04X}", opcode);
+            if device.cpu_state() == CpuState::WaitingForKeypress
+            {
+                step(&mut device, 2);
+                assert_eq!(device.instructions_executed(), 1, "{:04X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn the_default_font_is_at_0x50()
+    {
+        //LD F V0
+        let mut device: Chip8 = device_with(&[0xF0, 0x29]);
+        device.memory[0x50] = 0x00;
+        device.hard_reset();
+        assert_eq!(&device.memory[0x50..0x55], &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+
+        device.load_rom(&[0xF0, 0x29]).unwrap();
+        step(&mut device, 1);
+        assert_eq!(device.index(), FONT_BASE);
+    }
+
+    #[test]
+    fn fx29_points_into_a_custom_font()
+    {
+        let font: Vec<u8> = (0..FONT_SIZE as u8).collect();
+
+        //LD V0 02; LD F V0
+        let mut device: Chip8 = device_with(&[0x60, 0x02, 0xF0, 0x29]);
+        assert_eq!(device.load_font(&font, 0x100), Ok(()));
+        step(&mut device, 2);
+        assert_eq!(device.index(), 0x10A);
+        assert_eq!(device.font_data(), font.as_slice());
+
+        assert_eq!(device.load_font(&font, 0xFF0), Err(LoadError::TooLarge { len: FONT_SIZE }));
+        device.hard_reset();
+        assert_eq!(&device.font_data()[..5], &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    }
+
+    #[test]
+    fn last_instruction_skipped_reports_whether_se_skipped()
+    {
+        //LD V1 05; SE V1 05; CLS; SE V1 06
+        let mut device: Chip8 = device_with(&[0x61, 0x05, 0x31, 0x05, 0x00, 0xE0, 0x31, 0x06]);
+        step(&mut device, 1);
+        assert!(!device.last_instruction_skipped());
+        step(&mut device, 1);
+        assert!(device.last_instruction_skipped());
+        step(&mut device, 1);
+        assert!(!device.last_instruction_skipped());
+        assert_eq!(device.program_counter(), 0x208);
+    }
+
+    #[test]
+    fn write_memory_copies_blocks_that_fit()
+    {
+        let blob: [u8; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let mut device: Chip8 = Chip8::builder().build();
+        assert!(device.write_memory(0x300, &blob));
+        assert_eq!(&device.memory[0x300..0x30A], &blob);
+
+        assert!(!device.write_memory(0xFFA, &blob));
+        assert!(device.memory[0xFFA..MEMORY_SIZE].iter().all(|byte| *byte == 0));
+        assert!(device.write_memory(0xFF6, &blob));
+        assert_eq!(&device.memory[0xFF6..MEMORY_SIZE], &blob);
+    }
+}
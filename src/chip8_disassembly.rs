@@ -1,55 +1,317 @@
 //! For converting Chip8 machine code into a assembly language.
 
+use crate::instruction::{decode, Instruction};
+
 pub fn disassemble(opcode: u16) -> String
 {
-    //! Disassembles the provided opcode.
-
-    //Split the 16-byte opcode into four 4-bit nibbles. This will allow us to use pattern matching to detect the opcode.
-    let nibble3: u8 = ((opcode & 0xF000) >> 12) as u8;
-    let nibble2: u8 = ((opcode & 0x0F00) >> 8)  as u8;
-    let nibble1: u8 = ((opcode & 0xF0F0) >> 4)  as u8;
-    let nibble0: u8 = ((opcode & 0xF00F) >> 0)  as u8;
-
-    //Decode the current instruction then execute the instruction.
-    let instruction_string: String = match (nibble3, nibble2, nibble1, nibble0)
-    {
-        (0x0, 0x0, 0xE, 0x0) => String::from("CLS"), //CLS
-        (0x0, 0x0, 0xE, 0xE) => String::from("RET"), //RET
-        (0x0,   _,   _,   _) => format!("SYS {address:X}", address=(opcode & 0x0FFF)),  //SYS nnn
-        (0x1,   _,   _,   _) => format!("JP {address:X}",  address=(opcode & 0x0FFF)),  //JP nnn
-        (0x2,   _,   _,   _) => format!("CALL {address:X}", address=(opcode & 0x0FFF)), //CALL nnn
-        (0x3,   _,   _,   _) => format!("SE {register:X} {value:X}",  register=nibble2, value=(opcode & 0x00FF)),  //SE x nn
-        (0x4,   _,   _,   _) => format!("SNE {register:X} {value:X}",  register=nibble2, value=(opcode & 0x00FF)), //SNE x nn
-        (0x5,   _,   _, 0x0) => format!("SE {register1:X} {register2:X}",  register1=nibble2, register2=nibble1),  //SE x y
-        (0x6,   _,   _,   _) => format!("LD {register:X} {value:X}",  register=nibble2, value=(opcode & 0x00FF)),   //LD x nn
-        (0x7,   _,   _,   _) => format!("ADD {register:X} {value:X}",  register=nibble2, value=(opcode & 0x00FF)),  //ADD v nn
-        (0x8,   _,   _, 0x0) => format!("LD {register1:X} {register2:X}",  register1=nibble2, register2=nibble1),  //LD x y
-        (0x8,   _,   _, 0x1) => format!("OR {register1:X} {register2:X}",  register1=nibble2, register2=nibble1),  //OR x y
-        (0x8,   _,   _, 0x2) => format!("AND {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //AND x y
-        (0x8,   _,   _, 0x3) => format!("XOR {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //XOR x y
-        (0x8,   _,   _, 0x4) => format!("ADD {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //ADD x y
-        (0x8,   _,   _, 0x5) => format!("SUB {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //SUB x y
-        (0x8,   _,   _, 0x6) => format!("SHR {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //SHR x y
-        (0x8,   _,   _, 0x7) => format!("SUBN {register1:X} {register2:X}",  register1=nibble2, register2=nibble1),//SUBN x y
-        (0x8,   _,   _, 0xE) => format!("SHL {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //SHL x y
-        (0x9,   _,   _, 0x0) => format!("SNE {register1:X} {register2:X}",  register1=nibble2, register2=nibble1), //SNE x y
-        (0xA,   _,   _,   _) => format!("LD I {value:X}", value=(opcode & 0x0FFF)),                                 //LD x nn
-        (0xB,   _,   _,   _) => format!("JP V0 {value:X}", value=(opcode & 0x0FFF)),                                //JP vx nn
-        (0xC,   _,   _,   _) => format!("RND {register1:X}", register1=nibble2),                                   //RND x
-        (0xD,   _,   _,   _) => format!("SNE {register1:X} {register2:X} {value:X}",  register1=nibble2, register2=nibble1, value=nibble0), //DRW x y n
-        (0xE,   _, 0x9, 0xE) => format!("SKP {register1:X}", register1=nibble2),
-        (0xE,   _, 0xA, 0x1) => format!("SKNP {register1:X}", register1=nibble2),
-        (0xF,   _, 0x0, 0x7) => format!("LD {register1:X} DT", register1=nibble2),
-        (0xF,   _, 0x0, 0xA) => format!("LD {register1:X} K", register1=nibble2),
-        (0xF,   _, 0x1, 0x5) => format!("LD DT {register1:X}", register1=nibble2),
-        (0xF,   _, 0x1, 0x8) => format!("LD ST {register1:X}", register1=nibble2),
-        (0xF,   _, 0x1, 0xE) => format!("ADD I {register1:X}", register1=nibble2),
-        (0xF,   _, 0x2, 0x9) => format!("LD F {register1:X}", register1=nibble2),
-        (0xF,   _, 0x3, 0x3) => format!("LD B {register1:X}", register1=nibble2),
-        (0xF,   _, 0x5, 0x5) => format!("LD [I] {register1:X}", register1=nibble2),
-        (0xF,   _, 0x6, 0x5) => format!("LD {register1:X} [I]", register1=nibble2),
-        (  _,   _,   _,   _) => (String::from("?")),
-    };
-
-    instruction_string
-}
\ No newline at end of file
+    //! Disassembles the provided opcode. Registers and numbers are both written as bare hexadecimal, such as "LD 1 FF" or
+    //! "DRW 1 2 5", which assemble reads back. Opcodes that instruction::decode doesn't recognize disassemble as "?".
+
+    let instruction: Instruction = decode(opcode);
+    let mnemonic: &str = instruction.mnemonic();
+    match instruction
+    {
+        Instruction::Cls | Instruction::Ret | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Exit |
+        Instruction::Low | Instruction::High | Instruction::Audio | Instruction::Unknown(_) => String::from(mnemonic),
+
+        Instruction::ScrollDown {n} | Instruction::Plane {n} => format!("{} {:X}", mnemonic, n),
+        Instruction::Sys(nnn) | Instruction::Jp(nnn) | Instruction::Call(nnn) => format!("{} {:X}", mnemonic, nnn),
+
+        Instruction::SeVx {x, kk} | Instruction::SneVx {x, kk} | Instruction::LdVx {x, kk} | Instruction::AddVx {x, kk} |
+        Instruction::RndVx {x, kk} => format!("{} {:X} {:X}", mnemonic, x, kk),
+
+        Instruction::SeVxVy {x, y} | Instruction::SaveVxVy {x, y} | Instruction::LoadVxVy {x, y} | Instruction::LdVxVy {x, y} |
+        Instruction::OrVxVy {x, y} | Instruction::AndVxVy {x, y}  | Instruction::XorVxVy {x, y}  | Instruction::AddVxVy {x, y} |
+        Instruction::SubVxVy {x, y} | Instruction::ShrVx {x, y}   | Instruction::SubnVxVy {x, y} | Instruction::ShlVx {x, y}   |
+        Instruction::SneVxVy {x, y} => format!("{} {:X} {:X}", mnemonic, x, y),
+
+        Instruction::LdI(nnn)           => format!("LD I {:X}", nnn),
+        Instruction::JpV0(nnn)          => format!("JP V0 {:X}", nnn),
+        Instruction::DrwVxVy {x, y, n}  => format!("DRW {:X} {:X} {:X}", x, y, n),
+        Instruction::SkpVx {x} | Instruction::SknpVx {x} | Instruction::Pitch {x} => format!("{} {:X}", mnemonic, x),
+        Instruction::LdVxDt {x}         => format!("LD {:X} DT", x),
+        Instruction::LdVxK {x}          => format!("LD {:X} K", x),
+        Instruction::LdDtVx {x}         => format!("LD DT {:X}", x),
+        Instruction::LdStVx {x}         => format!("LD ST {:X}", x),
+        Instruction::AddIVx {x}         => format!("ADD I {:X}", x),
+        Instruction::LdFVx {x}          => format!("LD F {:X}", x),
+        Instruction::LdBVx {x}          => format!("LD B {:X}", x),
+        Instruction::LdIVx {x}          => format!("LD [I] {:X}", x),
+        Instruction::LdVxI {x}          => format!("LD {:X} [I]", x)
+    }
+}
+
+/// Broad groupings of the Chip-8 instruction set, useful for profiling where a program spends its time.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum OpcodeCategory
+{
+    Flow,       // Jumps, calls, returns, and conditional skips
+    Arithmetic, // Register loads, ALU operations, and RND
+    Memory,     // Anything reading or writing I or main memory
+    Display,    // CLS and DRW
+    Input,      // Keypad skips and waits
+    Timer,      // Delay and sound timer access, and the XO-CHIP audio pitch
+    Unknown
+}
+
+pub fn opcode_category(opcode: u16) -> OpcodeCategory
+{
+    //! Returns the category the provided opcode belongs to.
+
+    match decode(opcode)
+    {
+        Instruction::Cls | Instruction::ScrollDown {..} | Instruction::ScrollRight | Instruction::ScrollLeft |
+        Instruction::Low | Instruction::High | Instruction::DrwVxVy {..} | Instruction::Plane {..} => OpcodeCategory::Display,
+
+        Instruction::Ret | Instruction::Exit | Instruction::Sys(_) | Instruction::Jp(_) | Instruction::Call(_) |
+        Instruction::SeVx {..} | Instruction::SneVx {..} | Instruction::SeVxVy {..} | Instruction::SneVxVy {..} |
+        Instruction::JpV0(_) => OpcodeCategory::Flow,
+
+        Instruction::LdVx {..} | Instruction::AddVx {..} | Instruction::LdVxVy {..} | Instruction::OrVxVy {..} |
+        Instruction::AndVxVy {..} | Instruction::XorVxVy {..} | Instruction::AddVxVy {..} | Instruction::SubVxVy {..} |
+        Instruction::ShrVx {..} | Instruction::SubnVxVy {..} | Instruction::ShlVx {..} | Instruction::RndVx {..} => OpcodeCategory::Arithmetic,
+
+        Instruction::SaveVxVy {..} | Instruction::LoadVxVy {..} | Instruction::LdI(_) | Instruction::Audio | Instruction::AddIVx {..} |
+        Instruction::LdFVx {..} | Instruction::LdBVx {..} | Instruction::LdIVx {..} | Instruction::LdVxI {..} => OpcodeCategory::Memory,
+
+        Instruction::SkpVx {..} | Instruction::SknpVx {..} | Instruction::LdVxK {..} => OpcodeCategory::Input,
+
+        Instruction::LdVxDt {..} | Instruction::LdDtVx {..} | Instruction::LdStVx {..} | Instruction::Pitch {..} => OpcodeCategory::Timer,
+
+        Instruction::Unknown(_) => OpcodeCategory::Unknown
+    }
+}
+
+/// Where a jump goes, as returned by jump_targets.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum JumpTarget
+{
+    Constant(u16), // JP nnn and CALL nnn always go to nnn
+    Dynamic(u16)   // JP V0, nnn goes to nnn plus V0, so where it lands can't be known without running the program
+}
+
+pub fn jump_targets(bytes: &[u8], base: u16) -> Vec<(u16, JumpTarget)>
+{
+    //! Returns a (source address, target) pair for every JP, CALL, and JP V0 in the provided program, where base is the address
+    //! the program is loaded at. JP V0 is reported as a JumpTarget::Dynamic holding its base address, so a program containing one
+    //! has edges that can't be found statically.
+
+    let mut targets: Vec<(u16, JumpTarget)> = Vec::new();
+    for (i, word) in bytes.chunks_exact(2).enumerate()
+    {
+        let opcode: u16 = ((word[0] as u16) << 8) | (word[1] as u16);
+        let source: u16 = base.wrapping_add((i * 2) as u16);
+        match decode(opcode)
+        {
+            Instruction::Jp(nnn) | Instruction::Call(nnn) => targets.push((source, JumpTarget::Constant(nnn))),
+            Instruction::JpV0(nnn)                         => targets.push((source, JumpTarget::Dynamic(nnn))),
+            _ => ()
+        }
+    }
+
+    targets
+}
+
+pub fn next_instruction(bytes: &[u8], offset: usize) -> usize
+{
+    //! Returns the offset of the next instruction boundary at or after the provided offset, or bytes.len() if there is none.
+    //! Chip-8 instructions are two bytes long and normally word aligned, so this simply rounds the offset up to the next even
+    //! number. Data blobs of an even length are not detected and skipped over.
+
+    let aligned: usize = offset + (offset % 2);
+    if aligned >= bytes.len()
+    {
+        bytes.len()
+    }
+    else
+    {
+        aligned
+    }
+}
+
+// Every instruction's opcode pattern, as (mask, pattern, mnemonic), for verify_decode_matches_disasm. The table is written out from
+// the instruction set listings rather than built from instruction::decode, so that a mistake in either one shows up as a mismatch.
+// Where patterns overlap, the first match wins.
+const OPCODE_PATTERNS: [(u16, u16, &str); 46] =
+[
+    (0xFFFF, 0x00E0, "CLS"),  (0xFFFF, 0x00EE, "RET"),  (0xFFF0, 0x00C0, "SCD"),  (0xFFFF, 0x00FB, "SCR"),   (0xFFFF, 0x00FC, "SCL"),
+    (0xFFFF, 0x00FD, "EXIT"), (0xFFFF, 0x00FE, "LOW"),  (0xFFFF, 0x00FF, "HIGH"), (0xF000, 0x0000, "SYS"),   (0xF000, 0x1000, "JP"),
+    (0xF000, 0x2000, "CALL"), (0xF000, 0x3000, "SE"),   (0xF000, 0x4000, "SNE"),  (0xF00F, 0x5000, "SE"),    (0xF00F, 0x5002, "SAVE"),
+    (0xF00F, 0x5003, "LOAD"), (0xF000, 0x6000, "LD"),   (0xF000, 0x7000, "ADD"),  (0xF00F, 0x8000, "LD"),    (0xF00F, 0x8001, "OR"),
+    (0xF00F, 0x8002, "AND"),  (0xF00F, 0x8003, "XOR"),  (0xF00F, 0x8004, "ADD"),  (0xF00F, 0x8005, "SUB"),   (0xF00F, 0x8006, "SHR"),
+    (0xF00F, 0x8007, "SUBN"), (0xF00F, 0x800E, "SHL"),  (0xF00F, 0x9000, "SNE"),  (0xF000, 0xA000, "LD"),    (0xF000, 0xB000, "JP"),
+    (0xF000, 0xC000, "RND"),  (0xF000, 0xD000, "DRW"),  (0xF0FF, 0xE09E, "SKP"),  (0xF0FF, 0xE0A1, "SKNP"),  (0xF0FF, 0xF001, "PLANE"),
+    (0xFFFF, 0xF002, "AUDIO"), (0xF0FF, 0xF007, "LD"),  (0xF0FF, 0xF00A, "LD"),   (0xF0FF, 0xF015, "LD"),    (0xF0FF, 0xF018, "LD"),
+    (0xF0FF, 0xF01E, "ADD"),  (0xF0FF, 0xF029, "LD"),   (0xF0FF, 0xF033, "LD"),   (0xF0FF, 0xF03A, "PITCH"), (0xF0FF, 0xF055, "LD"),
+    (0xF0FF, 0xF065, "LD")
+];
+
+pub fn verify_decode_matches_disasm(opcode: u16) -> bool
+{
+    //! Checks that instruction::decode and disassemble agree with the instruction set listings on the mnemonic of the provided
+    //! opcode. Intended for tests guarding against the decoder, which the interpreter executes from, and the disassembler drifting
+    //! away from the listings. An opcode that isn't in the listings must decode as Instruction::Unknown and disassemble as "?".
+
+    let expected: &str = OPCODE_PATTERNS.iter()
+        .find(|(mask, pattern, _)| (opcode & mask) == *pattern)
+        .map_or("?", |(_, _, mnemonic)| *mnemonic);
+    let disassembly: String = disassemble(opcode);
+    let disassembled: &str = disassembly.split(' ').next().unwrap_or("");
+
+    (decode(opcode).mnemonic() == expected) && (disassembled == expected)
+}
+
+pub fn disassemble_program(bytes: &[u8], base: u16, flag_out_of_range: bool) -> Vec<(u16, String)>
+{
+    //! Disassembles the provided program, returning an (address, instruction) pair for every instruction, where base is the address
+    //! the program is loaded at. When flag_out_of_range is set, JP and CALL instructions targeting an address outside of the program
+    //! are marked with an "; out of range" comment, as they usually indicate data being disassembled or a bug.
+
+    let program_end: u32 = (base as u32) + (bytes.len() as u32);
+    let mut listing: Vec<(u16, String)> = Vec::with_capacity(bytes.len() / 2);
+
+    for (i, word) in bytes.chunks_exact(2).enumerate()
+    {
+        let opcode: u16 = ((word[0] as u16) << 8) | (word[1] as u16);
+        let address: u16 = base.wrapping_add((i * 2) as u16);
+        let mut line: String = disassemble(opcode);
+
+        if flag_out_of_range && ((opcode & 0xF000) == 0x1000 || (opcode & 0xF000) == 0x2000)
+        {
+            let target: u32 = (opcode & 0x0FFF) as u32;
+            if (target < (base as u32)) || (target >= program_end)
+            {
+                line.push_str(" ; out of range");
+            }
+        }
+
+        listing.push((address, line));
+    }
+
+    listing
+}
+
+pub fn disassemble_rom(bytes: &[u8], start_addr: u16) -> Vec<(u16, String)>
+{
+    //! Disassembles the provided ROM, returning an (address, instruction) pair for every instruction, where start_addr is the address
+    //! the ROM is loaded at. (usually 0x200) A trailing byte that doesn't make up a whole instruction is listed as a "DB" pseudo-op.
+
+    let mut listing: Vec<(u16, String)> = disassemble_program(bytes, start_addr, false);
+    if let [byte] = bytes.chunks_exact(2).remainder()
+    {
+        let address: u16 = start_addr.wrapping_add((bytes.len() - 1) as u16);
+        listing.push((address, format!("DB {byte:X}", byte=byte)));
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn decode_matches_disassembly_for_every_opcode()
+    {
+        for opcode in 0..=0xFFFF
+        {
+            assert!(verify_decode_matches_disasm(opcode), "{:04X} disassembles as {}", opcode, disassemble(opcode));
+        }
+    }
+
+    #[test]
+    fn xo_chip_opcodes_disassemble()
+    {
+        assert_eq!(disassemble(0xF201), "PLANE 2");
+        assert_eq!(disassemble(0xF002), "AUDIO");
+        assert_eq!(disassemble(0xF13A), "PITCH 1");
+    }
+
+    #[test]
+    fn jump_targets_lists_every_jump()
+    {
+        //JP 206; CALL 300; JP V0 400; LD V1 2
+        let program: [u8; 8] = [0x12, 0x06, 0x23, 0x00, 0xB4, 0x00, 0x61, 0x02];
+        assert_eq!(jump_targets(&program, 0x200), vec![(0x200, JumpTarget::Constant(0x206)), (0x202, JumpTarget::Constant(0x300)),
+                                                       (0x204, JumpTarget::Dynamic(0x400))]);
+    }
+
+    #[test]
+    fn opcodes_are_categorized()
+    {
+        assert_eq!(opcode_category(0x1200), OpcodeCategory::Flow);
+        assert_eq!(opcode_category(0x8124), OpcodeCategory::Arithmetic);
+        assert_eq!(opcode_category(0xD125), OpcodeCategory::Display);
+        assert_eq!(opcode_category(0x00EE), OpcodeCategory::Flow);
+        assert_eq!(opcode_category(0xF155), OpcodeCategory::Memory);
+        assert_eq!(opcode_category(0xE19E), OpcodeCategory::Input);
+        assert_eq!(opcode_category(0xF115), OpcodeCategory::Timer);
+        assert_eq!(opcode_category(0x5001), OpcodeCategory::Unknown);
+    }
+
+    #[test]
+    fn unknown_opcodes_disassemble_as_a_question_mark()
+    {
+        for opcode in 0..=0xFFFF
+        {
+            let disassembly: String = disassemble(opcode);
+            match decode(opcode)
+            {
+                Instruction::Unknown(_) => assert_eq!(disassembly, "?"),
+                instruction             => assert!(disassembly.starts_with(instruction.mnemonic()), "{:04X}", opcode)
+            }
+        }
+    }
+
+    #[test]
+    fn next_instruction_rounds_up_to_an_even_offset()
+    {
+        let bytes: [u8; 6] = [0; 6];
+        assert_eq!(next_instruction(&bytes, 3), 4);
+        assert_eq!(next_instruction(&bytes, 4), 4);
+        assert_eq!(next_instruction(&bytes, 5), 6);
+        assert_eq!(next_instruction(&bytes, 9), 6);
+    }
+
+    #[test]
+    fn disassemble_program_flags_jumps_past_the_program()
+    {
+        //JP 210; CALL 200
+        let program: [u8; 4] = [0x12, 0x10, 0x22, 0x00];
+        assert_eq!(disassemble_program(&program, 0x200, true), vec![(0x200, String::from("JP 210 ; out of range")),
+                                                                    (0x202, String::from("CALL 200"))]);
+        assert_eq!(disassemble_program(&program, 0x200, false), vec![(0x200, String::from("JP 210")), (0x202, String::from("CALL 200"))]);
+    }
+
+    #[test]
+    fn operands_come_from_the_right_nibbles()
+    {
+        assert_eq!(disassemble(0x8124), "ADD 1 2");
+        assert_eq!(disassemble(0xF129), "LD F 1");
+        assert_eq!(disassemble(0xD123), "DRW 1 2 3");
+        assert_eq!(disassemble(0x3A5B), "SE A 5B");
+        assert_eq!(disassemble(0xA9BC), "LD I 9BC");
+    }
+
+    #[test]
+    fn drw_disassembles_as_drw()
+    {
+        assert_eq!(disassemble(0xD125), "DRW 1 2 5");
+        assert_eq!(disassemble(0x9120), "SNE 1 2");
+    }
+
+    #[test]
+    fn disassemble_rom_lists_a_trailing_byte_as_db()
+    {
+        //LD 0 1; JP 200; and a stray AB
+        let rom: [u8; 5] = [0x60, 0x01, 0x12, 0x00, 0xAB];
+        assert_eq!(disassemble_rom(&rom, 0x200), vec![(0x200, String::from("LD 0 1")), (0x202, String::from("JP 200")),
+                                                      (0x204, String::from("DB AB"))]);
+        assert_eq!(disassemble_rom(&rom[..4], 0x200).len(), 2);
+    }
+}
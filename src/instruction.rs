@@ -0,0 +1,325 @@
+//! Structured representation of Chip-8 instructions.
+
+/// A decoded Chip-8 instruction. x and y are register numbers, kk is an 8-bit immediate value, and n is a 4-bit immediate value.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Instruction
+{
+    Cls,                            // 00E0
+    Ret,                            // 00EE
+    ScrollDown {n: u8},             // 00Cn (Super-CHIP)
+    ScrollRight,                    // 00FB (Super-CHIP)
+    ScrollLeft,                     // 00FC (Super-CHIP)
+    Exit,                           // 00FD (Super-CHIP)
+    Low,                            // 00FE (Super-CHIP)
+    High,                           // 00FF (Super-CHIP)
+    Sys(u16),                       // 0nnn
+    Jp(u16),                        // 1nnn
+    Call(u16),                      // 2nnn
+    SeVx     {x: u8, kk: u8},       // 3xkk
+    SneVx    {x: u8, kk: u8},       // 4xkk
+    SeVxVy   {x: u8, y: u8},        // 5xy0
+    SaveVxVy {x: u8, y: u8},        // 5xy2 (XO-CHIP)
+    LoadVxVy {x: u8, y: u8},        // 5xy3 (XO-CHIP)
+    LdVx     {x: u8, kk: u8},       // 6xkk
+    AddVx    {x: u8, kk: u8},       // 7xkk
+    LdVxVy   {x: u8, y: u8},        // 8xy0
+    OrVxVy   {x: u8, y: u8},        // 8xy1
+    AndVxVy  {x: u8, y: u8},        // 8xy2
+    XorVxVy  {x: u8, y: u8},        // 8xy3
+    AddVxVy  {x: u8, y: u8},        // 8xy4
+    SubVxVy  {x: u8, y: u8},        // 8xy5
+    ShrVx    {x: u8, y: u8},        // 8xy6
+    SubnVxVy {x: u8, y: u8},        // 8xy7
+    ShlVx    {x: u8, y: u8},        // 8xyE
+    SneVxVy  {x: u8, y: u8},        // 9xy0
+    LdI(u16),                       // Annn
+    JpV0(u16),                      // Bnnn
+    RndVx    {x: u8, kk: u8},       // Cxkk
+    DrwVxVy  {x: u8, y: u8, n: u8}, // Dxyn
+    SkpVx    {x: u8},               // Ex9E
+    SknpVx   {x: u8},               // ExA1
+    Plane    {n: u8},               // Fn01 (XO-CHIP)
+    Audio,                          // F002 (XO-CHIP)
+    LdVxDt   {x: u8},               // Fx07
+    LdVxK    {x: u8},               // Fx0A
+    LdDtVx   {x: u8},               // Fx15
+    LdStVx   {x: u8},               // Fx18
+    AddIVx   {x: u8},               // Fx1E
+    LdFVx    {x: u8},               // Fx29
+    LdBVx    {x: u8},               // Fx33
+    Pitch    {x: u8},               // Fx3A (XO-CHIP)
+    LdIVx    {x: u8},               // Fx55
+    LdVxI    {x: u8},               // Fx65
+    Unknown(u16)
+}
+
+impl Instruction
+{
+    /// Returns the instruction's assembly mnemonic, without operands.
+    pub fn mnemonic(&self) -> &'static str
+    {
+        match self
+        {
+            Instruction::Cls                 => "CLS",
+            Instruction::Ret                 => "RET",
+            Instruction::ScrollDown {..}     => "SCD",
+            Instruction::ScrollRight         => "SCR",
+            Instruction::ScrollLeft          => "SCL",
+            Instruction::Exit                => "EXIT",
+            Instruction::Low                 => "LOW",
+            Instruction::High                => "HIGH",
+            Instruction::Sys(_)              => "SYS",
+            Instruction::Jp(_)               => "JP",
+            Instruction::Call(_)             => "CALL",
+            Instruction::SeVx {..}           => "SE",
+            Instruction::SneVx {..}          => "SNE",
+            Instruction::SeVxVy {..}         => "SE",
+            Instruction::SaveVxVy {..}       => "SAVE",
+            Instruction::LoadVxVy {..}       => "LOAD",
+            Instruction::LdVx {..}           => "LD",
+            Instruction::AddVx {..}          => "ADD",
+            Instruction::LdVxVy {..}         => "LD",
+            Instruction::OrVxVy {..}         => "OR",
+            Instruction::AndVxVy {..}        => "AND",
+            Instruction::XorVxVy {..}        => "XOR",
+            Instruction::AddVxVy {..}        => "ADD",
+            Instruction::SubVxVy {..}        => "SUB",
+            Instruction::ShrVx {..}          => "SHR",
+            Instruction::SubnVxVy {..}       => "SUBN",
+            Instruction::ShlVx {..}          => "SHL",
+            Instruction::SneVxVy {..}        => "SNE",
+            Instruction::LdI(_)              => "LD",
+            Instruction::JpV0(_)             => "JP",
+            Instruction::RndVx {..}          => "RND",
+            Instruction::DrwVxVy {..}        => "DRW",
+            Instruction::SkpVx {..}          => "SKP",
+            Instruction::SknpVx {..}         => "SKNP",
+            Instruction::Plane {..}          => "PLANE",
+            Instruction::Audio               => "AUDIO",
+            Instruction::LdVxDt {..}         => "LD",
+            Instruction::LdVxK {..}          => "LD",
+            Instruction::LdDtVx {..}         => "LD",
+            Instruction::LdStVx {..}         => "LD",
+            Instruction::AddIVx {..}         => "ADD",
+            Instruction::LdFVx {..}          => "LD",
+            Instruction::LdBVx {..}          => "LD",
+            Instruction::Pitch {..}          => "PITCH",
+            Instruction::LdIVx {..}          => "LD",
+            Instruction::LdVxI {..}          => "LD",
+            Instruction::Unknown(_)          => "?"
+        }
+    }
+}
+
+/// Errors returned by validate_opcode.
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum OpcodeError
+{
+    UnknownOpcode(u16),  // The opcode isn't part of any supported instruction set
+    InvalidRegister(u8)  // A register operand was greater than 0xF
+}
+
+pub fn decode(opcode: u16) -> Instruction
+{
+    //! Decodes the provided opcode. Opcodes that aren't part of any supported instruction set decode to Instruction::Unknown.
+
+    //Split the 16-byte opcode into four 4-bit nibbles. This will allow us to use pattern matching to detect the opcode.
+    let nibble3: u8 = ((opcode & 0xF000) >> 12) as u8;
+    let nibble2: u8 = ((opcode & 0x0F00) >> 8)  as u8;
+    let nibble1: u8 = ((opcode & 0x00F0) >> 4)  as u8;
+    let nibble0: u8 =  (opcode & 0x000F)        as u8;
+
+    let nnn: u16 = opcode & 0x0FFF;
+    let kk:  u8  = (opcode & 0x00FF) as u8;
+
+    match (nibble3, nibble2, nibble1, nibble0)
+    {
+        //The 0x00nn opcodes are told apart by their whole low byte. Anything not listed is a SYS call.
+        (0x0, 0x0,   _,   _) => match kk
+        {
+            0xE0        => Instruction::Cls,
+            0xEE        => Instruction::Ret,
+            0xC0..=0xCF => Instruction::ScrollDown {n: nibble0},
+            0xFB        => Instruction::ScrollRight,
+            0xFC        => Instruction::ScrollLeft,
+            0xFD        => Instruction::Exit,
+            0xFE        => Instruction::Low,
+            0xFF        => Instruction::High,
+            _           => Instruction::Sys(nnn)
+        },
+        (0x0,   _,   _,   _) => Instruction::Sys(nnn),
+        (0x1,   _,   _,   _) => Instruction::Jp(nnn),
+        (0x2,   _,   _,   _) => Instruction::Call(nnn),
+        (0x3,   _,   _,   _) => Instruction::SeVx     {x: nibble2, kk},
+        (0x4,   _,   _,   _) => Instruction::SneVx    {x: nibble2, kk},
+        (0x5,   _,   _, 0x0) => Instruction::SeVxVy   {x: nibble2, y: nibble1},
+        (0x5,   _,   _, 0x2) => Instruction::SaveVxVy {x: nibble2, y: nibble1},
+        (0x5,   _,   _, 0x3) => Instruction::LoadVxVy {x: nibble2, y: nibble1},
+        (0x6,   _,   _,   _) => Instruction::LdVx     {x: nibble2, kk},
+        (0x7,   _,   _,   _) => Instruction::AddVx    {x: nibble2, kk},
+        (0x8,   _,   _, 0x0) => Instruction::LdVxVy   {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x1) => Instruction::OrVxVy   {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x2) => Instruction::AndVxVy  {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x3) => Instruction::XorVxVy  {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x4) => Instruction::AddVxVy  {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x5) => Instruction::SubVxVy  {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x6) => Instruction::ShrVx    {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0x7) => Instruction::SubnVxVy {x: nibble2, y: nibble1},
+        (0x8,   _,   _, 0xE) => Instruction::ShlVx    {x: nibble2, y: nibble1},
+        (0x9,   _,   _, 0x0) => Instruction::SneVxVy  {x: nibble2, y: nibble1},
+        (0xA,   _,   _,   _) => Instruction::LdI(nnn),
+        (0xB,   _,   _,   _) => Instruction::JpV0(nnn),
+        (0xC,   _,   _,   _) => Instruction::RndVx    {x: nibble2, kk},
+        (0xD,   _,   _,   _) => Instruction::DrwVxVy  {x: nibble2, y: nibble1, n: nibble0},
+        (0xE,   _, 0x9, 0xE) => Instruction::SkpVx    {x: nibble2},
+        (0xE,   _, 0xA, 0x1) => Instruction::SknpVx   {x: nibble2},
+        (0xF,   _, 0x0, 0x1) => Instruction::Plane    {n: nibble2},
+        (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
+        (0xF,   _, 0x0, 0x7) => Instruction::LdVxDt   {x: nibble2},
+        (0xF,   _, 0x0, 0xA) => Instruction::LdVxK    {x: nibble2},
+        (0xF,   _, 0x1, 0x5) => Instruction::LdDtVx   {x: nibble2},
+        (0xF,   _, 0x1, 0x8) => Instruction::LdStVx   {x: nibble2},
+        (0xF,   _, 0x1, 0xE) => Instruction::AddIVx   {x: nibble2},
+        (0xF,   _, 0x2, 0x9) => Instruction::LdFVx    {x: nibble2},
+        (0xF,   _, 0x3, 0x3) => Instruction::LdBVx    {x: nibble2},
+        (0xF,   _, 0x3, 0xA) => Instruction::Pitch    {x: nibble2},
+        (0xF,   _, 0x5, 0x5) => Instruction::LdIVx    {x: nibble2},
+        (0xF,   _, 0x6, 0x5) => Instruction::LdVxI    {x: nibble2},
+        (  _,   _,   _,   _) => Instruction::Unknown(opcode)
+    }
+}
+
+pub fn is_implemented(opcode: u16) -> bool
+{
+    //! Returns true if the provided opcode decodes to an instruction, rather than Instruction::Unknown. XO-CHIP and Super-CHIP
+    //! instructions count as implemented, even though they are only executed in their modes.
+
+    decode(opcode) != Instruction::Unknown(opcode)
+}
+
+pub fn validate_opcode(opcode: u16) -> Result<(), OpcodeError>
+{
+    //! Checks that the provided opcode decodes to a known instruction whose register operands are in range. Register operands are
+    //! single nibbles, so they are always 0x0 to 0xF, but they are checked here so that this stays true if wider operands are added.
+
+    let registers: Vec<u8> = match decode(opcode)
+    {
+        Instruction::Unknown(_) => return Err(OpcodeError::UnknownOpcode(opcode)),
+
+        Instruction::SeVxVy {x, y} | Instruction::SaveVxVy {x, y} | Instruction::LoadVxVy {x, y} | Instruction::LdVxVy {x, y} |
+        Instruction::OrVxVy {x, y} | Instruction::AndVxVy {x, y}  | Instruction::XorVxVy {x, y}  | Instruction::AddVxVy {x, y} |
+        Instruction::SubVxVy {x, y} | Instruction::ShrVx {x, y}   | Instruction::SubnVxVy {x, y} | Instruction::ShlVx {x, y}   |
+        Instruction::SneVxVy {x, y} | Instruction::DrwVxVy {x, y, ..} => vec![x, y],
+
+        Instruction::SeVx {x, ..} | Instruction::SneVx {x, ..} | Instruction::LdVx {x, ..} | Instruction::AddVx {x, ..} |
+        Instruction::RndVx {x, ..} | Instruction::SkpVx {x}    | Instruction::SknpVx {x}  | Instruction::LdVxDt {x}     |
+        Instruction::LdVxK {x}     | Instruction::LdDtVx {x}   | Instruction::LdStVx {x}  | Instruction::AddIVx {x}     |
+        Instruction::LdFVx {x}     | Instruction::LdBVx {x}    | Instruction::Pitch {x}   | Instruction::LdIVx {x}      |
+        Instruction::LdVxI {x} => vec![x],
+
+        _ => Vec::new()
+    };
+
+    match registers.into_iter().find(|register| *register > 0xF)
+    {
+        Some(register) => Err(OpcodeError::InvalidRegister(register)),
+        None           => Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn decode_recognizes_every_instruction()
+    {
+        let table: [(u16, Instruction); 48] =
+        [
+            (0x00E0, Instruction::Cls),
+            (0x00EE, Instruction::Ret),
+            (0x00C3, Instruction::ScrollDown {n: 3}),
+            (0x00FB, Instruction::ScrollRight),
+            (0x00FC, Instruction::ScrollLeft),
+            (0x00FD, Instruction::Exit),
+            (0x00FE, Instruction::Low),
+            (0x00FF, Instruction::High),
+            (0x0123, Instruction::Sys(0x123)),
+            (0x1234, Instruction::Jp(0x234)),
+            (0x2345, Instruction::Call(0x345)),
+            (0x31AB, Instruction::SeVx     {x: 1, kk: 0xAB}),
+            (0x42CD, Instruction::SneVx    {x: 2, kk: 0xCD}),
+            (0x5120, Instruction::SeVxVy   {x: 1, y: 2}),
+            (0x5122, Instruction::SaveVxVy {x: 1, y: 2}),
+            (0x5123, Instruction::LoadVxVy {x: 1, y: 2}),
+            (0x6A12, Instruction::LdVx     {x: 0xA, kk: 0x12}),
+            (0x7B34, Instruction::AddVx    {x: 0xB, kk: 0x34}),
+            (0x8120, Instruction::LdVxVy   {x: 1, y: 2}),
+            (0x8121, Instruction::OrVxVy   {x: 1, y: 2}),
+            (0x8122, Instruction::AndVxVy  {x: 1, y: 2}),
+            (0x8123, Instruction::XorVxVy  {x: 1, y: 2}),
+            (0x8124, Instruction::AddVxVy  {x: 1, y: 2}),
+            (0x8125, Instruction::SubVxVy  {x: 1, y: 2}),
+            (0x8126, Instruction::ShrVx    {x: 1, y: 2}),
+            (0x8127, Instruction::SubnVxVy {x: 1, y: 2}),
+            (0x812E, Instruction::ShlVx    {x: 1, y: 2}),
+            (0x9120, Instruction::SneVxVy  {x: 1, y: 2}),
+            (0xA456, Instruction::LdI(0x456)),
+            (0xB567, Instruction::JpV0(0x567)),
+            (0xC3F0, Instruction::RndVx    {x: 3, kk: 0xF0}),
+            (0xD125, Instruction::DrwVxVy  {x: 1, y: 2, n: 5}),
+            (0xE49E, Instruction::SkpVx    {x: 4}),
+            (0xE5A1, Instruction::SknpVx   {x: 5}),
+            (0xF201, Instruction::Plane    {n: 2}),
+            (0xF002, Instruction::Audio),
+            (0xF607, Instruction::LdVxDt   {x: 6}),
+            (0xF70A, Instruction::LdVxK    {x: 7}),
+            (0xF815, Instruction::LdDtVx   {x: 8}),
+            (0xF918, Instruction::LdStVx   {x: 9}),
+            (0xFA1E, Instruction::AddIVx   {x: 0xA}),
+            (0xFB29, Instruction::LdFVx    {x: 0xB}),
+            (0xFC33, Instruction::LdBVx    {x: 0xC}),
+            (0xFD3A, Instruction::Pitch    {x: 0xD}),
+            (0xFE55, Instruction::LdIVx    {x: 0xE}),
+            (0xFF65, Instruction::LdVxI    {x: 0xF}),
+            (0x5121, Instruction::Unknown(0x5121)),
+            (0xF102, Instruction::Unknown(0xF102))
+        ];
+
+        for (opcode, instruction) in table.iter()
+        {
+            assert_eq!(decode(*opcode), *instruction, "{:04X}", opcode);
+            assert_eq!(is_implemented(*opcode), *instruction != Instruction::Unknown(*opcode));
+        }
+    }
+
+    #[test]
+    fn validate_opcode_rejects_unknown_opcodes()
+    {
+        assert_eq!(validate_opcode(0xD125), Ok(()));
+        assert_eq!(validate_opcode(0xE1FF), Err(OpcodeError::UnknownOpcode(0xE1FF)));
+    }
+
+    #[test]
+    fn is_implemented_rejects_opcodes_that_do_not_decode()
+    {
+        assert!(is_implemented(0xD125));
+        assert!(is_implemented(0x00FF));
+        assert!(!is_implemented(0x5001));
+        assert!(!is_implemented(0xE1FF));
+    }
+
+    #[test]
+    fn validate_opcode_accepts_ld_and_rejects_5xy1()
+    {
+        assert_eq!(validate_opcode(0x6123), Ok(()));
+        assert_eq!(validate_opcode(0x8120), Ok(()));
+        assert_eq!(validate_opcode(0x5001), Err(OpcodeError::UnknownOpcode(0x5001)));
+    }
+}
@@ -1,3 +1,5 @@
 #![crate_name = "rusty_chip8"]
+pub mod assemble;
 pub mod chip8;
-pub mod chip8_disassembly;
\ No newline at end of file
+pub mod chip8_disassembly;
+pub mod instruction;
\ No newline at end of file